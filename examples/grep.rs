@@ -1,4 +1,3 @@
-use leben_regex::UnicodeCodepoint;
 use std::io::Read;
 
 fn main() -> Result<(), anyhow::Error> {
@@ -12,24 +11,22 @@ fn main() -> Result<(), anyhow::Error> {
     let mut buffer = Vec::new();
     std::io::stdin().read_to_end(&mut buffer)?;
 
-    let string = leben_regex::decode_utf8(&buffer)?;
+    // the input is searched without decoding all of it first
+    let bytes = buffer.strip_prefix(b"\xef\xbb\xbf").unwrap_or(&buffer);
 
-    if let Some((match_index, len)) = regex.find(&string) {
-        let match_end = match_index + len;
-        let print_start = rfind_lf(&string, match_index);
-        let print_end = find_lf(&string, match_end);
+    if let Some(found) = regex.find_utf8(bytes)? {
+        let (match_index, match_end) = (found.start(), found.end());
+        let print_start = rfind_lf(bytes, match_index);
+        let print_end = find_lf(bytes, match_end);
         print!(
             "{}",
-            leben_regex::encode_utf8_string(&string[print_start..match_index])
+            String::from_utf8_lossy(&bytes[print_start..match_index])
         );
         print!(
             "\x1b[91m{}\x1b[m",
-            leben_regex::encode_utf8_string(&string[match_index..match_end])
-        );
-        println!(
-            "{}",
-            leben_regex::encode_utf8_string(&string[match_end..print_end])
+            String::from_utf8_lossy(&bytes[match_index..match_end])
         );
+        println!("{}", String::from_utf8_lossy(&bytes[match_end..print_end]));
     } else {
         println!("No match found!");
     }
@@ -37,22 +34,16 @@ fn main() -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-fn find_lf(string: &[UnicodeCodepoint], index: usize) -> usize {
-    let lf_chars: [UnicodeCodepoint; 2] =
-        [UnicodeCodepoint::from('\n'), UnicodeCodepoint::from('\r')];
-    string[index..]
+fn find_lf(bytes: &[u8], index: usize) -> usize {
+    bytes[index..]
         .iter()
-        .zip(index..)
-        .find(|(c, _)| lf_chars.contains(*c))
-        .map_or(string.len(), |(_, i)| i)
+        .position(|b| matches!(b, b'\n' | b'\r'))
+        .map_or(bytes.len(), |i| index + i)
 }
 
-fn rfind_lf(string: &[UnicodeCodepoint], index: usize) -> usize {
-    let lf_chars: [UnicodeCodepoint; 2] =
-        [UnicodeCodepoint::from('\n'), UnicodeCodepoint::from('\r')];
-    string[..index]
+fn rfind_lf(bytes: &[u8], index: usize) -> usize {
+    bytes[..index]
         .iter()
-        .zip(0..index)
-        .rfind(|(c, _)| lf_chars.contains(*c))
-        .map_or(0, |(_, i)| i + 1)
+        .rposition(|b| matches!(b, b'\n' | b'\r'))
+        .map_or(0, |i| i + 1)
 }
@@ -0,0 +1,47 @@
+//! Splits the input at its first zero byte into a pattern and a string, and
+//! checks that `Regex::find`, `Regex::find_all` and `Regex::test` agree with
+//! the brute-force reference matcher in `tests/reference` on every pattern
+//! that compiles.
+#![no_main]
+
+#[path = "../../tests/reference/mod.rs"]
+mod reference;
+
+use leben_regex::{Regex, RegexOptions, decode_utf8};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some(split) = data.iter().position(|b| *b == 0) else {
+        return;
+    };
+    let (pattern, string) = (&data[..split], &data[split + 1..]);
+    // the reference matcher redoes every nested repetition from every
+    // index, so keep both the string and the automaton small
+    let Ok(string) = decode_utf8(string) else {
+        return;
+    };
+    if string.len() > 64 {
+        return;
+    }
+    let options = RegexOptions {
+        max_repeat: 16,
+        max_states: 256,
+        ..RegexOptions::default()
+    };
+    let Ok(regex) = Regex::new_with_options(pattern, &options) else {
+        return;
+    };
+    let ast = Regex::parse_ast(pattern).unwrap();
+    assert_eq!(
+        regex.find(&string),
+        reference::find(&ast.root.node, &string, false)
+    );
+    assert_eq!(
+        regex.find_all(&string),
+        reference::find_all(&ast.root.node, &string, false)
+    );
+    assert_eq!(
+        regex.test(&string),
+        reference::test(&ast.root.node, &string, false)
+    );
+});
@@ -0,0 +1,51 @@
+//! Feeds arbitrary bytes to `Regex::new`, which should return `Ok` or `Err`
+//! but never panic. Patterns that compile are also run on a short input.
+//!
+//! The first byte picks the options, and whether the rest of the pattern is
+//! wrapped in nested groups that can all be empty, which is where capturing
+//! has to follow the longest chains of epsilon edges.
+#![no_main]
+
+use leben_regex::{Regex, RegexOptions, decode_utf8};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&flags, pattern)) = data.split_first() else {
+        return;
+    };
+    let default = RegexOptions::default();
+    let options = RegexOptions {
+        case_insensitive: flags & 1 != 0,
+        max_states: if flags & 2 != 0 {
+            64
+        } else {
+            default.max_states
+        },
+        expected_states: match (flags >> 2) & 3 {
+            0 => default.expected_states,
+            1 => 0,
+            2 => 1 << 40,
+            _ => usize::MAX,
+        },
+        ..default
+    };
+    let pattern = if flags & 16 != 0 {
+        let depth = usize::from(flags >> 5) * 16;
+        [
+            "(".repeat(depth).as_bytes(),
+            pattern,
+            "|)".repeat(depth).as_bytes(),
+        ]
+        .concat()
+    } else {
+        pattern.to_vec()
+    };
+
+    let _ = Regex::new_with_warnings(&pattern);
+    if let Ok(regex) = Regex::new_with_options(&pattern, &options) {
+        let string = decode_utf8(b"ab,a").unwrap();
+        let _ = regex.find(&string);
+        let _ = regex.captures(&string);
+        let _ = regex.captures_find_iter(&string).count();
+    }
+});
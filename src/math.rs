@@ -1,11 +1,15 @@
-#[derive(Clone, Debug)]
+use alloc::boxed::Box;
+use alloc::vec;
+use core::ops::Index;
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct BitMatrix {
     pub size_i: usize,
     pub size_j: usize,
     el: Box<[bool]>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct BitVector {
     pub size: usize,
     el: Box<[bool]>,
@@ -30,25 +34,50 @@ impl BitMatrix {
         }
     }
 
+    /// Creates a square `size` by `size` matrix with the entries `(i, j)` in
+    /// `edges` set to `true`. Panics on out-of-range indices, like `set`.
+    pub fn from_edges(
+        size: usize,
+        edges: impl IntoIterator<Item = (usize, usize)>,
+    ) -> BitMatrix {
+        let mut matrix = BitMatrix::new(size, size);
+        for (i, j) in edges {
+            matrix.set(i, j, true);
+        }
+        matrix
+    }
+
+    #[cfg(any(test, feature = "rayon"))]
     fn index_iter(&self) -> impl Iterator<Item = (usize, usize)> + use<> {
         let sy = self.size_j;
         (0..self.size_i).flat_map(move |i| (0..sy).map(move |j| (i, j)))
     }
 
+    #[cfg(any(test, feature = "rayon"))]
     pub fn enumerate_iter(
         &self,
     ) -> impl Iterator<Item = ((usize, usize), &bool)> {
         self.index_iter().zip(self.el.iter())
     }
 
+    #[cfg(test)]
     pub fn enumerate_iter_mut(
         &mut self,
     ) -> impl Iterator<Item = ((usize, usize), &mut bool)> {
         self.index_iter().zip(self.el.iter_mut())
     }
 
-    pub fn reset(&mut self) {
-        self.enumerate_iter_mut().for_each(|(_, v)| *v = false)
+    /// returns: the entries `(i, 0)` to `(i, size_j - 1)`
+    pub fn row(&self, i: usize) -> impl Iterator<Item = bool> {
+        assert!(i < self.size_i);
+        let start = self.index(i, 0);
+        self.el[start..start + self.size_j].iter().copied()
+    }
+
+    /// returns: the entries `(0, j)` to `(size_i - 1, j)`
+    pub fn col(&self, j: usize) -> impl Iterator<Item = bool> {
+        assert!(j < self.size_j);
+        (0..self.size_i).map(move |i| self.el[self.index(i, j)])
     }
 
     pub fn set(&mut self, i: usize, j: usize, value: bool) {
@@ -57,29 +86,42 @@ impl BitMatrix {
         self.el[self.index(i, j)] = value;
     }
 
+    #[cfg(test)]
     pub fn get(&self, i: usize, j: usize) -> bool {
         assert!(i < self.size_i);
         assert!(j < self.size_j);
         self.el[self.index(i, j)]
     }
 
-    pub fn add(a: &BitMatrix, b: &BitMatrix, c: &mut BitMatrix) {
-        assert_eq!(a.size_i, b.size_i);
-        assert_eq!(a.size_j, b.size_j);
-        assert_eq!(a.size_i, c.size_i);
-        assert_eq!(a.size_j, c.size_j);
-        c.enumerate_iter_mut()
-            .for_each(|((i, j), value)| *value = a.get(i, j) || b.get(i, j));
+    /// returns: the element-wise OR of all `matrices`, which is the
+    /// transition matrix of taking any one of their transitions
+    ///
+    /// Panics if `matrices` is empty or the dimensions differ
+    pub fn or_all(matrices: &[&BitMatrix]) -> BitMatrix {
+        let (first, rest) = matrices
+            .split_first()
+            .expect("at least one matrix should be given");
+        let mut c = (*first).clone();
+        for m in rest {
+            assert_eq!(c.size_i, m.size_i);
+            assert_eq!(c.size_j, m.size_j);
+            c.el.iter_mut().zip(m.el.iter()).for_each(|(c, m)| *c |= *m);
+        }
+        c
     }
 
+    #[cfg(test)]
     pub fn mult(a: &BitMatrix, b: &BitMatrix, c: &mut BitMatrix) {
         assert_eq!(a.size_i, b.size_j);
         assert_eq!(c.size_i, b.size_i);
         assert_eq!(c.size_j, a.size_j);
+        // checked once here instead of on every access in the loop below
+        assert_eq!(a.size_i, a.size_j);
+        assert_eq!(b.size_i, b.size_j);
         let n = a.size_i;
         c.enumerate_iter_mut().for_each(|((i, j), value)| {
             for k in 0..n {
-                if a.get(i, k) && b.get(k, j) {
+                if a.el[a.index(i, k)] && b.el[b.index(k, j)] {
                     *value = true;
                     return;
                 }
@@ -97,6 +139,11 @@ impl BitVector {
         }
     }
 
+    #[cfg(test)]
+    pub fn from_bools(values: &[bool]) -> BitVector {
+        values.iter().copied().collect()
+    }
+
     pub fn enumerate_iter(&self) -> impl Iterator<Item = (usize, &bool)> {
         (0..self.size).zip(self.el.iter())
     }
@@ -121,20 +168,41 @@ impl BitVector {
         self.el[i]
     }
 
+    #[cfg(test)]
     pub fn add(a: &BitVector, b: &BitVector, c: &mut BitVector) {
         assert_eq!(a.size, b.size);
         assert_eq!(a.size, c.size);
-        c.enumerate_iter_mut()
-            .for_each(|(i, value)| *value = a.get(i) || b.get(i));
+        for ((c, a), b) in c.el.iter_mut().zip(a.el.iter()).zip(b.el.iter()) {
+            *c = *a || *b;
+        }
     }
 
     pub fn mult(a: &BitMatrix, b: &BitVector, c: &mut BitVector) {
         assert_eq!(a.size_i, b.size);
         assert_eq!(a.size_j, c.size);
+        // checked once here instead of on every access in the loop below
+        assert_eq!(a.size_i, a.size_j);
+        // few states are usually active, so only their columns are ORed into
+        // `c`; this runs once per token, so nothing is allocated
+        c.reset();
+        for (k, _) in b.enumerate_iter().filter(|(_, value)| **value) {
+            for (i, value) in c.enumerate_iter_mut() {
+                *value |= a.el[a.index(i, k)];
+            }
+        }
+    }
+
+    /// Like `mult`, but multiplies `b` by the transpose of `a`, which follows
+    /// the transitions of `a` backwards
+    pub fn mult_transposed(a: &BitMatrix, b: &BitVector, c: &mut BitVector) {
+        assert_eq!(a.size_j, b.size);
+        assert_eq!(a.size_i, c.size);
+        // checked once here instead of on every access in the loop below
+        assert_eq!(a.size_i, a.size_j);
         let n = a.size_i;
-        c.enumerate_iter_mut().for_each(|(i, value)| {
+        c.enumerate_iter_mut().for_each(|(j, value)| {
             for k in 0..n {
-                if a.get(i, k) && b.get(k) {
+                if a.el[a.index(k, j)] && b.el[k] {
                     *value = true;
                     return;
                 }
@@ -143,9 +211,63 @@ impl BitVector {
         })
     }
 
+    /// Clears every entry of `self` that isn't set in `mask`
+    pub fn and_assign(&mut self, mask: &BitVector) {
+        assert_eq!(self.size, mask.size);
+        for (a, b) in self.el.iter_mut().zip(mask.el.iter()) {
+            *a &= *b;
+        }
+    }
+
+    /// Sets every entry of `self` that is set in `other`
+    ///
+    /// returns: whether any entry of `self` changed
+    pub fn or_assign(&mut self, other: &BitVector) -> bool {
+        assert_eq!(self.size, other.size);
+        let mut changed = false;
+        for (a, b) in self.el.iter_mut().zip(other.el.iter()) {
+            changed |= *b && !*a;
+            *a |= *b;
+        }
+        changed
+    }
+
+    /// returns: whether no entry is set
+    pub fn is_empty(&self) -> bool {
+        !self.el.iter().any(|value| *value)
+    }
+
     pub fn dot(a: &BitVector, b: &BitVector) -> bool {
         assert_eq!(a.size, b.size);
-        a.enumerate_iter().any(|(i, value)| *value && b.get(i))
+        a.el.iter().zip(b.el.iter()).any(|(a, b)| *a && *b)
+    }
+}
+
+/// The same as `get`, including its bounds checks
+impl Index<(usize, usize)> for BitMatrix {
+    type Output = bool;
+
+    fn index(&self, (i, j): (usize, usize)) -> &bool {
+        assert!(i < self.size_i);
+        assert!(j < self.size_j);
+        &self.el[BitMatrix::index(self, i, j)]
+    }
+}
+
+/// The same as `get`, including its bounds check
+impl Index<usize> for BitVector {
+    type Output = bool;
+
+    fn index(&self, i: usize) -> &bool {
+        assert!(i < self.size);
+        &self.el[i]
+    }
+}
+
+impl FromIterator<bool> for BitVector {
+    fn from_iter<T: IntoIterator<Item = bool>>(iter: T) -> Self {
+        let el: Box<[bool]> = iter.into_iter().collect();
+        BitVector { size: el.len(), el }
     }
 }
 
@@ -183,27 +305,93 @@ impl NfaVector {
         self.el[i]
     }
 
+    pub fn as_slice(&self) -> &[Option<usize>] {
+        &self.el
+    }
+
+    /// returns: the smallest value of any active entry, or `None` if no entry
+    /// is active
+    pub fn min_value(&self) -> Option<usize> {
+        self.el.iter().copied().fold(None, min_some)
+    }
+
+    /// Clears every entry of `self` that isn't set in `mask`
+    pub fn and_assign(&mut self, mask: &BitVector) {
+        assert_eq!(self.size, mask.size);
+        for (a, b) in self.el.iter_mut().zip(mask.el.iter()) {
+            if !*b {
+                *a = None;
+            }
+        }
+    }
+
     pub fn mult(a: &BitMatrix, b: &NfaVector, c: &mut NfaVector) {
         assert_eq!(a.size_i, b.size);
         assert_eq!(a.size_j, c.size);
+        // checked once here instead of on every access in the loop below
+        assert_eq!(a.size_i, a.size_j);
+        // only the columns of active states are looked at, see
+        // `BitVector::mult`
+        c.reset();
+        for (k, start) in b.enumerate_iter() {
+            let Some(start) = *start else { continue };
+            for (i, value) in c.enumerate_iter_mut() {
+                if a.el[a.index(i, k)] {
+                    *value = min_some(*value, Some(start));
+                }
+            }
+        }
+    }
+
+    /// Like `mult`, but multiplies `b` by the transpose of `a`, which follows
+    /// the transitions of `a` backwards
+    pub fn mult_transposed(a: &BitMatrix, b: &NfaVector, c: &mut NfaVector) {
+        assert_eq!(a.size_j, b.size);
+        assert_eq!(a.size_i, c.size);
+        // checked once here instead of on every access in the loop below
+        assert_eq!(a.size_i, a.size_j);
         let n = a.size_i;
-        c.enumerate_iter_mut().for_each(|(i, old_value)| {
+        c.enumerate_iter_mut().for_each(|(j, old_value)| {
             let mut value = None;
             for k in 0..n {
-                if a.get(i, k) {
-                    value = min_some(value, b.get(k));
+                if a.el[a.index(k, j)] {
+                    value = min_some(value, b.el[k]);
                 }
             }
             *old_value = value;
         })
     }
 
+    /// Lowers every entry of `self` to the corresponding entry of `other` if
+    /// that is smaller, treating `None` as larger than any value
+    ///
+    /// returns: whether any entry of `self` changed
+    pub fn min_assign(&mut self, other: &NfaVector) -> bool {
+        assert_eq!(self.size, other.size);
+        let mut changed = false;
+        for (a, b) in self.el.iter_mut().zip(other.el.iter()) {
+            let min = min_some(*a, *b);
+            changed |= min != *a;
+            *a = min;
+        }
+        changed
+    }
+
     pub fn dot(a: &NfaVector, b: &BitVector) -> Option<usize> {
+        NfaVector::dot_which(a, b).map(|(value, _)| value)
+    }
+
+    /// Like `dot`, but also tells which entry the value came from
+    ///
+    /// returns: the smallest value of any entry of `a` that is set in `b`, and
+    /// the first index it's at
+    pub fn dot_which(a: &NfaVector, b: &BitVector) -> Option<(usize, usize)> {
         assert_eq!(a.size, b.size);
         a.el.iter()
             .zip(b.el.iter())
-            .map(|(a, b)| a.and_then(|a| b.then_some(a)))
-            .fold(None, min_some)
+            .enumerate()
+            .filter_map(|(i, (a, b))| a.filter(|_| *b).map(|a| (a, i)))
+            .min()
     }
 }
 
@@ -214,3 +402,372 @@ fn min_some(a: Option<usize>, b: Option<usize>) -> Option<usize> {
         (Some(x), Some(y)) => Some(x.min(y)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// returns: a fixed pseudorandom `n`x`n` matrix for `seed`, with about
+    /// `edges_per_state` edges per state
+    fn random_matrix(seed: u64, n: usize, edges_per_state: usize) -> BitMatrix {
+        let mut seed = seed;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) as usize % n
+        };
+        BitMatrix::from_edges(
+            n,
+            (0..edges_per_state * n).map(|_| (next(), next())),
+        )
+    }
+
+    #[test]
+    fn bit_matrix_from_edges() {
+        let edges = [(0, 1), (1, 2), (2, 2), (3, 0)];
+        let mut expected = BitMatrix::new(4, 4);
+        for (i, j) in edges {
+            expected.set(i, j, true);
+        }
+        let matrix = BitMatrix::from_edges(4, edges);
+        for i in 0..4 {
+            for j in 0..4 {
+                assert_eq!(expected.get(i, j), matrix.get(i, j));
+            }
+        }
+
+        let empty = BitMatrix::from_edges(3, []);
+        assert!(empty.enumerate_iter().all(|(_, v)| !*v));
+    }
+
+    #[test]
+    fn bit_matrix_or_all() {
+        // transitions of a `[abc]`-style class, one matrix per token
+        let a = BitMatrix::from_edges(3, [(1, 0)]);
+        let b = BitMatrix::from_edges(3, [(1, 0), (2, 1)]);
+        let c = BitMatrix::from_edges(3, [(2, 2)]);
+        let merged = BitMatrix::or_all(&[&a, &b, &c]);
+
+        for bits in 0..8 {
+            let mut v = BitVector::new(3);
+            for i in 0..3 {
+                v.set(i, bits & (1 << i) != 0);
+            }
+
+            let mut expected = BitVector::new(3);
+            let mut temp = BitVector::new(3);
+            for m in [&a, &b, &c] {
+                BitVector::mult(m, &v, &mut temp);
+                let prev = expected.clone();
+                BitVector::add(&prev, &temp, &mut expected);
+            }
+
+            let mut actual = BitVector::new(3);
+            BitVector::mult(&merged, &v, &mut actual);
+            for i in 0..3 {
+                assert_eq!(expected.get(i), actual.get(i));
+            }
+        }
+    }
+
+    #[test]
+    fn bit_matrix_row_col() {
+        let matrices = [
+            BitMatrix::from_edges(3, [(0, 1), (1, 2), (2, 2), (2, 0)]),
+            BitMatrix::from_edges(1, [(0, 0)]),
+            BitMatrix::new(2, 4),
+        ];
+        for m in &matrices {
+            for i in 0..m.size_i {
+                let row: Vec<_> = m.row(i).collect();
+                let expected: Vec<_> =
+                    (0..m.size_j).map(|j| m.get(i, j)).collect();
+                assert_eq!(row, expected);
+            }
+            for j in 0..m.size_j {
+                let col: Vec<_> = m.col(j).collect();
+                let expected: Vec<_> =
+                    (0..m.size_i).map(|i| m.get(i, j)).collect();
+                assert_eq!(col, expected);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn bit_matrix_row_out_of_range() {
+        _ = BitMatrix::new(2, 3).row(2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bit_matrix_get_out_of_range() {
+        // (0, 3) is inside the backing storage, but outside the matrix
+        _ = BitMatrix::new(2, 3).get(0, 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn bit_matrix_set_out_of_range() {
+        BitMatrix::new(2, 3).set(2, 0, true);
+    }
+
+    #[test]
+    fn bit_index() {
+        let m = BitMatrix::from_edges(3, [(0, 1), (2, 2), (1, 0)]);
+        for i in 0..3 {
+            for j in 0..3 {
+                assert_eq!(m[(i, j)], m.get(i, j));
+            }
+        }
+        let v = BitVector::from_bools(&[true, false, true]);
+        for i in 0..3 {
+            assert_eq!(v[i], v.get(i));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn bit_matrix_index_out_of_range() {
+        // (0, 3) is inside the backing storage, but outside the matrix
+        _ = BitMatrix::new(2, 3)[(0, 3)];
+    }
+
+    #[test]
+    #[should_panic]
+    fn bit_vector_index_out_of_range() {
+        _ = BitVector::new(3)[3];
+    }
+
+    #[test]
+    #[should_panic]
+    fn bit_vector_get_out_of_range() {
+        _ = BitVector::new(3).get(3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn nfa_vector_set_out_of_range() {
+        NfaVector::new(3).set(3, Some(0));
+    }
+
+    #[test]
+    fn bit_vector_mult_transposed() {
+        let a = BitMatrix::from_edges(3, [(0, 1), (1, 2), (2, 2), (1, 0)]);
+        let mut t = BitMatrix::new(3, 3);
+        for ((i, j), value) in a.enumerate_iter() {
+            t.set(j, i, *value);
+        }
+        for bits in 0..8 {
+            let v: BitVector = (0..3).map(|i| bits & (1 << i) != 0).collect();
+            let mut expected = BitVector::new(3);
+            BitVector::mult(&t, &v, &mut expected);
+            let mut actual = BitVector::new(3);
+            BitVector::mult_transposed(&a, &v, &mut actual);
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn nfa_vector_mult_transposed() {
+        let a = BitMatrix::from_edges(3, [(0, 1), (1, 2), (2, 2), (1, 0)]);
+        let mut t = BitMatrix::new(3, 3);
+        for ((i, j), value) in a.enumerate_iter() {
+            t.set(j, i, *value);
+        }
+        let mut v = NfaVector::new(3);
+        for values in [
+            [None, None, None],
+            [Some(5), None, Some(2)],
+            [Some(1), Some(3), Some(0)],
+        ] {
+            for (i, value) in values.into_iter().enumerate() {
+                v.set(i, value);
+            }
+            let mut expected = NfaVector::new(3);
+            NfaVector::mult(&t, &v, &mut expected);
+            let mut actual = NfaVector::new(3);
+            NfaVector::mult_transposed(&a, &v, &mut actual);
+            assert_eq!(expected.as_slice(), actual.as_slice());
+        }
+    }
+
+    #[test]
+    fn bit_matrix_mult() {
+        const N: usize = 100;
+        let a = random_matrix(7, N, 2);
+        let b = random_matrix(8, N, 2);
+        let mut c = BitMatrix::new(N, N);
+        BitMatrix::mult(&a, &b, &mut c);
+
+        for i in 0..N {
+            for j in 0..N {
+                let expected = (0..N).any(|k| a.get(i, k) && b.get(k, j));
+                assert_eq!(c.get(i, j), expected);
+            }
+        }
+        assert!(c.enumerate_iter().any(|(_, value)| *value));
+    }
+
+    #[test]
+    fn bit_vector_mult_sparse() {
+        const N: usize = 2000;
+        let a = random_matrix(1, N, 10);
+
+        let mut b = BitVector::new(N);
+        for k in [0, 7, 1234, N - 1] {
+            b.set(k, true);
+        }
+        let mut c = BitVector::new(N);
+        BitVector::mult(&a, &b, &mut c);
+
+        for i in 0..N {
+            let expected = (0..N).any(|k| a.get(i, k) && b.get(k));
+            assert_eq!(c.get(i), expected);
+        }
+        assert!(c.enumerate_iter().any(|(_, value)| *value));
+    }
+
+    #[test]
+    fn nfa_vector_mult_sparse() {
+        const N: usize = 2000;
+        let a = random_matrix(1, N, 10);
+
+        let mut b = NfaVector::new(N);
+        for (k, value) in [(0, 5), (7, 3), (1234, 9), (N - 1, 3)] {
+            b.set(k, Some(value));
+        }
+        let mut c = NfaVector::new(N);
+        NfaVector::mult(&a, &b, &mut c);
+
+        for i in 0..N {
+            let expected = (0..N)
+                .filter(|k| a.get(i, *k))
+                .filter_map(|k| b.get(k))
+                .min();
+            assert_eq!(c.get(i), expected);
+        }
+        assert!(c.min_value().is_some());
+    }
+
+    #[test]
+    fn nfa_vector_dot_which() {
+        let mut a = NfaVector::new(5);
+        a.set(0, Some(1));
+        a.set(1, Some(4));
+        a.set(2, Some(2));
+        a.set(3, Some(2));
+        a.set(4, Some(3));
+        let b = BitVector::from_bools(&[false, true, true, true, true]);
+        // state 0 has the smallest value, but isn't final
+        assert_eq!(NfaVector::dot_which(&a, &b), Some((2, 2)));
+        assert_eq!(NfaVector::dot(&a, &b), Some(2));
+
+        let b = BitVector::from_bools(&[false, true, false, false, false]);
+        assert_eq!(NfaVector::dot_which(&a, &b), Some((4, 1)));
+        assert_eq!(NfaVector::dot_which(&a, &BitVector::new(5)), None);
+        assert_eq!(NfaVector::dot_which(&NfaVector::new(5), &b), None);
+    }
+
+    #[test]
+    fn vector_assign() {
+        let mut a = BitVector::from_bools(&[true, false, false]);
+        assert!(a.or_assign(&BitVector::from_bools(&[true, true, false])));
+        assert!(!a.or_assign(&BitVector::from_bools(&[false, true, false])));
+        assert_eq!(a, BitVector::from_bools(&[true, true, false]));
+
+        let mut a = NfaVector::new(3);
+        a.set(0, Some(4));
+        let mut b = NfaVector::new(3);
+        b.set(0, Some(5));
+        b.set(1, Some(2));
+        assert!(a.min_assign(&b));
+        assert!(!a.min_assign(&b));
+        assert_eq!(a.get(0), Some(4));
+        assert_eq!(a.get(1), Some(2));
+        assert_eq!(a.get(2), None);
+    }
+
+    #[test]
+    fn bit_vector_from_iter() {
+        let values = [true, false, false, true, true];
+        let v: BitVector = values.iter().copied().collect();
+        assert_eq!(v.size, values.len());
+        let round_trip: Vec<_> = v.enumerate_iter().map(|(_, v)| *v).collect();
+        assert_eq!(round_trip, values);
+
+        let v = BitVector::from_bools(&values);
+        assert_eq!(v.size, values.len());
+        for (i, value) in v.enumerate_iter() {
+            assert_eq!(*value, values[i]);
+        }
+
+        assert_eq!(BitVector::from_bools(&[]).size, 0);
+    }
+
+    #[test]
+    fn and_assign() {
+        let mask = BitVector::from_bools(&[true, false, true]);
+        let mut v = BitVector::from_bools(&[true, true, false]);
+        v.and_assign(&mask);
+        assert_eq!(v, BitVector::from_bools(&[true, false, false]));
+
+        let mut v = NfaVector::new(3);
+        for i in 0..3 {
+            v.set(i, Some(i));
+        }
+        v.and_assign(&mask);
+        assert_eq!(v.as_slice(), [Some(0), None, Some(2)]);
+    }
+
+    #[test]
+    fn bit_vector_is_empty() {
+        assert!(BitVector::new(3).is_empty());
+        assert!(BitVector::new(0).is_empty());
+        assert!(!BitVector::from_bools(&[false, true, false]).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn bit_matrix_from_edges_out_of_range() {
+        _ = BitMatrix::from_edges(2, [(0, 2)]);
+    }
+
+    #[test]
+    fn bit_hash() {
+        extern crate std;
+        use core::hash::BuildHasher;
+        use std::collections::HashMap;
+        use std::hash::RandomState;
+
+        let state = RandomState::new();
+        let a = BitVector::from_bools(&[true, false, true]);
+        let b = BitVector::from_bools(&[true, false, true]);
+        assert_eq!(state.hash_one(&a), state.hash_one(&b));
+
+        let mut states = HashMap::new();
+        states.insert(a, 0);
+        states.insert(BitVector::from_bools(&[true, false]), 1);
+        states.insert(BitVector::from_bools(&[true, false, false]), 2);
+        assert_eq!(states.len(), 3);
+        assert_eq!(states[&b], 0);
+        assert_eq!(states.insert(b, 3), Some(0));
+
+        // matrices with the same entries but different shapes are different
+        let m = BitMatrix::from_edges(2, [(0, 1)]);
+        let mut transposed = BitMatrix::new(2, 2);
+        transposed.set(1, 0, true);
+        let mut wide = BitMatrix::new(1, 4);
+        wide.set(0, 1, true);
+        let mut matrices = HashMap::new();
+        matrices.insert(m.clone(), 0);
+        matrices.insert(transposed, 1);
+        matrices.insert(wide, 2);
+        assert_eq!(matrices.len(), 3);
+        assert_eq!(matrices[&BitMatrix::from_edges(2, [(0, 1)])], 0);
+        assert_eq!(
+            state.hash_one(&m),
+            state.hash_one(BitMatrix::from_edges(2, [(0, 1)]))
+        );
+    }
+}
@@ -1,255 +1,3121 @@
 use crate::math::{BitMatrix, BitVector, NfaVector};
-use crate::regex::graph::{Graph, NodeRef};
-use crate::regex::parse::{Atom, ConcatExpr, RegexAst};
+use crate::regex::graph::{CaptureScratch, CompiledGraph, Graph};
+#[cfg(feature = "std")]
+use crate::regex::parse::{AltExpr, RegexAst};
 use crate::utf8::{UnicodeCodepoint, Utf8DecodeError};
-use parsable::Parsable;
-use std::collections::HashMap;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use parsable::{Parsable, Span};
 
+#[cfg(feature = "std")]
 mod compile;
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
 mod graph;
-mod parse;
+mod iter;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod parse;
+mod pattern;
+#[cfg(feature = "std")]
+mod reader;
+mod replace;
+#[cfg(feature = "std")]
+mod warnings;
 
-pub struct Regex {
-    token_matrices: HashMap<UnicodeCodepoint, BitMatrix>,
+pub use iter::{CapturesIter, Split};
+pub use pattern::Pattern;
+#[cfg(feature = "std")]
+pub use warnings::Warning;
+
+/// A regex over tokens of any ordered type `T`, such as the tokens of a lexer
+/// or bytes, with the same automaton and matching as [`Regex`]. There's no
+/// text syntax for tokens that aren't codepoints, so it's built from a
+/// [`Pattern`] with [`GenericRegex::from_pattern`].
+pub struct GenericRegex<T> {
+    /// sorted, so that everything listing tokens is in the same order every
+    /// time; lookups are slower than hashing, but the alphabet is usually
+    /// small. Tokens with the same transitions share a matrix.
+    token_matrices: BTreeMap<T, Arc<BitMatrix>>,
+    /// transitions that only hold at the start of the input, if any
+    start_anchor: Option<BitMatrix>,
+    /// transitions that only hold at the end of the input, if any
+    end_anchor: Option<BitMatrix>,
     final_nodes: BitVector,
+    /// the states from which a final state can still be reached once some
+    /// input has been read; the others are dropped after every step
+    live_nodes: BitVector,
+    anchored_start: bool,
+    anchored_end: bool,
+    /// the uncollapsed graph with group boundaries as tagged epsilon edges,
+    /// if there are any groups
+    capture_graph: Option<Graph<T>>,
+    group_count: usize,
+    /// the number of every named group
+    group_names: BTreeMap<String, usize>,
+}
+
+/// A regex over Unicode codepoints, which can also be parsed from a pattern
+/// string
+pub type Regex = GenericRegex<UnicodeCodepoint>;
+
+/// Regexes are equal if they compile to the same automaton, which is not the
+/// same as the patterns being equal: `\@` and `@` compare equal. Patterns
+/// that match the same strings but compile to differently shaped automata,
+/// like `a|a` and `a`, compare unequal. Groups aren't compared either, so
+/// `(a)` and `a` compare equal.
+impl<T: PartialEq> PartialEq for GenericRegex<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.token_matrices == other.token_matrices
+            && self.start_anchor == other.start_anchor
+            && self.end_anchor == other.end_anchor
+            && self.final_nodes == other.final_nodes
+    }
 }
 
+impl<T: Eq> Eq for GenericRegex<T> {}
+
+#[cfg(feature = "std")]
 #[derive(Debug, thiserror::Error)]
 pub enum RegexParseError {
     #[error("parse error: 'expected regular expression'")]
     MissingParseResultError,
     #[error(
         "parse error at index {}: 'expected {}'",
-        .0.first().map_or(0, |e| e.source_position),
-        .0.first().map_or("", |e| &e.error[..]),
+        .stack.first().map_or(0, |e| e.source_position),
+        .stack.first().map_or("", |e| &e.error[..]),
     )]
-    ParseError(parsable::ParseErrorStack),
+    ParseError {
+        /// every error the parser ran into, the first one first
+        stack: parsable::ParseErrorStack,
+        /// the source around the first error, see [`RegexParseError::context`]
+        excerpt: String,
+    },
+}
+
+#[cfg(feature = "std")]
+impl RegexParseError {
+    /// returns: every error on the stack, not just the first one that
+    /// `Display` shows
+    pub fn details(&self) -> Vec<ParseErrorDetail> {
+        match self {
+            RegexParseError::MissingParseResultError => {
+                vec![ParseErrorDetail {
+                    position: None,
+                    message: String::from("expected regular expression"),
+                }]
+            }
+            RegexParseError::ParseError { stack, .. } => stack
+                .iter()
+                .map(|e| ParseErrorDetail {
+                    position: Some(e.source_position),
+                    message: format!("expected {}", e.error),
+                })
+                .collect(),
+        }
+    }
+
+    /// returns: the byte offset of the first error in the pattern, and the
+    /// part of the pattern around it, for pointing at the error in an editor;
+    /// `None` if the error has no position
+    pub fn context(&self) -> Option<(usize, String)> {
+        match self {
+            RegexParseError::MissingParseResultError => None,
+            RegexParseError::ParseError { stack, excerpt } => {
+                let position = stack.first()?.source_position;
+                Some((position, excerpt.clone()))
+            }
+        }
+    }
+}
+
+/// One of the errors returned by [`Regex::new_verbose`]
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseErrorDetail {
+    /// the byte index into the pattern, or `None` for errors found after
+    /// parsing, which don't belong to a single position
+    pub position: Option<usize>,
+    pub message: String,
+}
+
+/// A position in the input that a `^` or `$` matches
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "std", derive(serde::Serialize))]
+pub enum Anchor {
+    /// `^`, the start of the input
+    Start,
+    /// `$`, the end of the input
+    End,
+}
+
+/// A description of a compiled regex as a nondeterministic finite automaton,
+/// independent of the internal matrix representation.
+///
+/// States are numbered `0..state_count`, and state `0` is the start state.
+/// Matching begins with only the start state active; for each input token,
+/// the next set of active states is every `to` of an edge `(from, to, token)`
+/// whose `from` is active. Before the first token, and again after the last
+/// one, every `to` of an anchor edge `(from, to, anchor)` whose `from` is
+/// active and whose anchor holds there becomes active too, repeatedly. The
+/// input is accepted if any final state is active once it has been consumed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "std", derive(serde::Serialize))]
+pub struct AutomatonExport {
+    pub state_count: usize,
+    /// `(from, to, token)` triples, sorted
+    pub edges: Vec<(u32, u32, UnicodeCodepoint)>,
+    /// `(from, to, anchor)` triples, sorted
+    pub anchor_edges: Vec<(u32, u32, Anchor)>,
+    /// bit `i % 64` of word `i / 64` is set if state `i` is final
+    pub final_states: Vec<u64>,
+}
+
+/// Options for compiling a regex, see [`Regex::new_with_options`]
+#[derive(Clone, Debug)]
+pub struct RegexOptions {
+    /// the largest count allowed in `{n}`; every repetition is compiled to a
+    /// copy of the repeated expression, so this bounds the size of the
+    /// automaton for untrusted patterns
+    pub max_repeat: usize,
+    /// the largest number of states the automaton may have; every codepoint
+    /// in the regex gets a matrix with the square of this many entries
+    pub max_states: usize,
+    /// the largest number of entries of all transition matrices together;
+    /// codepoints with the same transitions share a matrix, but a pattern
+    /// with many different literals needs one matrix for each, so this
+    /// bounds the memory of patterns that stay under `max_states`
+    pub max_matrix_entries: usize,
+    /// the largest number of alternatives of all alternations in the pattern
+    /// together, where a pattern or group without `|` counts as one; every
+    /// alternative adds states, so this bounds wide generated patterns like
+    /// `a|b|c|...` before they are compiled
+    pub max_alternation_branches: usize,
+    /// whether every literal also matches its lowercase and uppercase forms
+    pub case_insensitive: bool,
+    /// the number of states to allocate room for before compiling, to avoid
+    /// growing the graph when the size of the pattern is known; doesn't
+    /// change what the regex matches, and room for more than `max_states`
+    /// is never allocated
+    pub expected_states: usize,
+}
+
+impl Default for RegexOptions {
+    fn default() -> Self {
+        RegexOptions {
+            max_repeat: 1000,
+            max_states: 4096,
+            max_matrix_entries: 1 << 28,
+            max_alternation_branches: 10_000,
+            case_insensitive: false,
+            expected_states: 0,
+        }
+    }
+}
+
+/// A match of a regex, as a range of indices into the searched string;
+/// these count codepoints, except for the methods that search UTF-8, which
+/// say that they count bytes
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Match {
+    start: usize,
+    len: usize,
+}
+
+impl Match {
+    /// returns: the index of the first codepoint of the match
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// returns: the number of codepoints in the match
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// returns: whether the match is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// returns: the index one past the last codepoint of the match
+    pub fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    /// returns: `start()..end()`, for slicing the searched string
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end()
+    }
+}
+
+/// The active states of a regex's automaton, for driving it by hand with
+/// [`Regex::initial_state`], [`Regex::step`] and [`Regex::is_accepting`]
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct State(BitVector);
+
+impl State {
+    /// returns: whether no state is active, after which no string read on
+    /// can match
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The vectors that [`Regex::find`] works in, kept between searches so that
+/// going through many matches doesn't allocate them for each
+struct FindScratch {
+    accumulator: NfaVector,
+    temp: NfaVector,
+}
+
+/// The reason [`Regex::find_with_reason`] didn't find a match
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FindFailure {
+    /// the input ended while a match attempt was still active
+    EndOfInput,
+    /// every match attempt was rejected by the automaton
+    Rejected,
+}
+
+/// How far [`Regex::test_partial`] got once the input ran out
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PartialMatch {
+    /// the entire input matches the regex
+    Complete,
+    /// the input doesn't match, but the automaton is still in a state from
+    /// which a final state can be reached, so more input could make it match
+    NeedsMore,
+    /// neither the input nor anything starting with it matches the regex
+    Rejected,
+}
+
+/// The error returned by [`Regex::find_with_budget`] when matching needs more
+/// steps than allowed
+#[derive(Copy, Clone, Debug, Eq, PartialEq, thiserror::Error)]
+#[error("matching exceeded the budget of {max_steps} steps")]
+pub struct BudgetExceeded {
+    pub max_steps: usize,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum RegexError {
-    #[error("{0}")]
-    ParseError(RegexParseError),
-    #[error("invalid utf8 codepoint: {0}")]
-    Utf8DecodeError(Utf8DecodeError),
+    #[cfg(feature = "std")]
+    #[error("invalid pattern")]
+    ParseError(#[from] RegexParseError),
+    #[error("invalid utf8 in pattern")]
+    Utf8DecodeError(#[from] Utf8DecodeError),
+    #[error("repetition count is larger than the maximum of {max}")]
+    RepeatTooLarge { max: usize },
+    #[error("automaton has more than the maximum of {max_states} states")]
+    AutomatonTooLarge { max_states: usize },
+    #[error(
+        "automaton's matrices have more than the maximum of {max_entries} \
+         entries"
+    )]
+    MatricesTooLarge { max_entries: usize },
+    #[error("pattern has more than the maximum of {max} alternatives")]
+    TooManyBranches { max: usize },
+    #[error("more than one group is named '{name}'")]
+    DuplicateGroupName { name: String },
 }
 
 impl Regex {
-    pub fn new_from_str(source: &str) -> Result<Regex, RegexParseError> {
-        Regex::new(source.as_bytes()).map_err(|e| match e {
-            RegexError::ParseError(e) => e,
-            RegexError::Utf8DecodeError(_) => panic!(
-                "valid UTF-8 string shouldn't result in UTF-8 decoding error"
-            ),
-        })
+    /// Like [`Regex::new`], for a pattern that is already a string. Since
+    /// 0.2.0, this returns a [`RegexError`] rather than a
+    /// [`RegexParseError`], since a valid pattern can still be too large to
+    /// compile.
+    #[cfg(feature = "std")]
+    pub fn new_from_str(source: &str) -> Result<Regex, RegexError> {
+        Regex::new(source.as_bytes())
+    }
+
+    /// Like [`Regex::new`], for a pattern that is already decoded
+    #[cfg(feature = "std")]
+    pub fn new_from_codepoints(
+        source: &[UnicodeCodepoint],
+    ) -> Result<Regex, RegexError> {
+        // codepoints always encode to valid UTF-8, so this doesn't lose
+        // anything; the parser only reads bytes
+        Regex::new(&crate::utf8::encode_utf8(source))
     }
 
+    #[cfg(feature = "std")]
     pub fn new(source: &[u8]) -> Result<Regex, RegexError> {
+        Regex::new_with_options(source, &RegexOptions::default())
+    }
+
+    /// Like [`Regex::new`], but every literal also matches its lowercase and
+    /// uppercase forms
+    #[cfg(feature = "std")]
+    pub fn new_case_insensitive(source: &[u8]) -> Result<Regex, RegexError> {
+        let options = RegexOptions {
+            case_insensitive: true,
+            ..RegexOptions::default()
+        };
+        Regex::new_with_options(source, &options)
+    }
+
+    /// Like [`Regex::new`], but allocates room for `expected_states` states
+    /// up front, see [`RegexOptions::expected_states`]
+    #[cfg(feature = "std")]
+    pub fn new_with_capacity(
+        source: &[u8],
+        expected_states: usize,
+    ) -> Result<Regex, RegexError> {
+        let options = RegexOptions {
+            expected_states,
+            ..RegexOptions::default()
+        };
+        Regex::new_with_options(source, &options)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn new_with_options(
+        source: &[u8],
+        options: &RegexOptions,
+    ) -> Result<Regex, RegexError> {
+        let regex = Regex::parse_ast(source).map_err(RegexError::ParseError)?;
+        Regex::from_alt_expr(&regex.root.node, options)
+    }
+
+    /// Like [`Regex::new`], but also returns warnings about parts of the
+    /// pattern that are likely mistakes, but still compile
+    #[cfg(feature = "std")]
+    pub fn new_with_warnings(
+        source: &[u8],
+    ) -> Result<(Regex, Vec<Warning>), RegexError> {
+        let ast = Regex::parse_ast(source).map_err(RegexError::ParseError)?;
+        let regex =
+            Regex::from_alt_expr(&ast.root.node, &RegexOptions::default())?;
+        Ok((regex, warnings::warnings(&ast.root.node)))
+    }
+
+    /// Like [`Regex::new`], but returns every error on the parser's error
+    /// stack instead of only the first. Parsing still stops at the first
+    /// position it can't continue from, so every error is about that
+    /// position; a second mistake later in the pattern isn't found until the
+    /// first is fixed.
+    #[cfg(feature = "std")]
+    pub fn new_verbose(source: &[u8]) -> Result<Regex, Vec<ParseErrorDetail>> {
+        let regex = Regex::parse_ast(source).map_err(|e| e.details())?;
+        Regex::from_alt_expr(&regex.root.node, &RegexOptions::default())
+            .map_err(|e| {
+                vec![ParseErrorDetail {
+                    position: None,
+                    message: e.to_string(),
+                }]
+            })
+    }
+
+    /// returns: the syntax tree of `source`, without compiling it
+    ///
+    /// The tree's types follow the grammar and change with it, so they
+    /// aren't covered by semver.
+    #[cfg(feature = "std")]
+    #[doc(hidden)]
+    pub fn parse_ast(source: &[u8]) -> Result<RegexAst, RegexParseError> {
         let mut stream = parsable::ScopedStream::new(source);
-        let outcome = RegexAst::parse(&mut stream);
-        let regex = match outcome {
-            None => {
-                return Err(RegexError::ParseError(
-                    RegexParseError::MissingParseResultError,
-                ));
-            }
-            Some(result) => match result {
-                Ok(regex) => regex,
-                Err(e) => {
-                    return Err(RegexError::ParseError(
-                        RegexParseError::ParseError(e),
-                    ));
-                }
-            },
+        parse_result(RegexAst::parse(&mut stream), source)
+    }
+
+    /// Parses a regex from the start of `source`, stopping at the first byte
+    /// that can't continue it (such as an unmatched `)`) instead of requiring
+    /// the whole input to be consumed.
+    ///
+    /// returns: the regex and the number of bytes of `source` it consumed
+    #[cfg(feature = "std")]
+    pub fn parse_prefix(source: &[u8]) -> Result<(Regex, usize), RegexError> {
+        let mut stream = parsable::ScopedStream::new(source);
+        let alt = parse_result(Span::<AltExpr>::parse(&mut stream), source)
+            .map_err(RegexError::ParseError)?;
+        let consumed = alt.span.len();
+        let regex = Regex::from_alt_expr(&alt.node, &RegexOptions::default())?;
+        Ok((regex, consumed))
+    }
+
+    #[cfg(feature = "std")]
+    fn from_alt_expr(
+        alt: &AltExpr,
+        options: &RegexOptions,
+    ) -> Result<Regex, RegexError> {
+        compile::compile(alt, options)
+    }
+
+    /// returns: a description of the compiled automaton, see
+    /// [`AutomatonExport`]
+    pub fn export(&self) -> AutomatonExport {
+        let state_count = self.final_nodes.size;
+
+        let mut edges = Vec::new();
+        for (token, matrix) in &self.token_matrices {
+            edges.extend(
+                matrix_edges(matrix).map(|(from, to)| (from, to, *token)),
+            );
+        }
+        edges.sort_unstable();
+
+        let mut anchor_edges = Vec::new();
+        for (anchor, matrix) in [
+            (Anchor::Start, &self.start_anchor),
+            (Anchor::End, &self.end_anchor),
+        ] {
+            if let Some(matrix) = matrix {
+                anchor_edges.extend(
+                    matrix_edges(matrix).map(|(from, to)| (from, to, anchor)),
+                );
+            }
+        }
+        anchor_edges.sort_unstable();
+
+        let mut final_states = vec![0_u64; state_count.div_ceil(64)];
+        for (i, value) in self.final_nodes.enumerate_iter() {
+            if *value {
+                final_states[i / 64] |= 1 << (i % 64);
+            }
+        }
+
+        AutomatonExport {
+            state_count,
+            edges,
+            anchor_edges,
+            final_states,
+        }
+    }
+
+    /// returns: whether all of `chars` matches the regex, see [`Regex::test`]
+    pub fn test_chars<I: IntoIterator<Item = char>>(&self, chars: I) -> bool {
+        self.test_iter(chars.into_iter().map(UnicodeCodepoint::from))
+    }
+
+    /// returns: whether all of `line` matches the regex, not counting a
+    /// trailing `\n` or `\r\n`
+    pub fn test_line(&self, line: &[UnicodeCodepoint]) -> bool {
+        self.test(strip_line_end(line))
+    }
+
+    /// Splits `input` into lines like [`str::lines`], at every `\n` or
+    /// `\r\n`, and searches each of them, the way `grep` does
+    ///
+    /// returns: the index of every line with a match, counting from 0, and
+    /// the codepoint range of the first match in it, relative to the start
+    /// of the line
+    pub fn grep_lines(
+        &self,
+        input: &[UnicodeCodepoint],
+    ) -> Vec<(usize, Range<usize>)> {
+        let newline = UnicodeCodepoint::from('\n');
+        input
+            .split_inclusive(|c| *c == newline)
+            .map(strip_line_end)
+            .enumerate()
+            .filter_map(|(i, line)| Some((i, self.find_match(line)?.range())))
+            .collect()
+    }
+
+    /// Like [`Regex::test_partial`], but reads the string one `char` at a
+    /// time
+    pub fn test_partial_chars<I: IntoIterator<Item = char>>(
+        &self,
+        chars: I,
+    ) -> PartialMatch {
+        self.test_partial_iter(chars.into_iter().map(UnicodeCodepoint::from))
+    }
+
+    /// Like [`Regex::find`], but reads the string one `char` at a time, so it
+    /// doesn't have to be collected first. Reading stops once the first match
+    /// is known.
+    ///
+    /// returns: the first match, if any, counted in `char`s from the start of
+    /// `chars`
+    pub fn find_chars<I: IntoIterator<Item = char>>(
+        &self,
+        chars: I,
+    ) -> Option<Match> {
+        let string = chars.into_iter().map(UnicodeCodepoint::from);
+        self.find_with_steps(
+            &mut self.find_scratch(),
+            string,
+            usize::MAX,
+            true,
+            &mut |_, _| {},
+        )
+        .expect("matching should take at most one step per codepoint")
+        .ok()
+        .map(|(start, len)| Match { start, len })
+    }
+
+    /// Like [`Regex::find`], but decodes `utf8` one codepoint at a time while
+    /// searching it, so that it doesn't have to be decoded all at once, such
+    /// as for a memory-mapped file. Reading stops once the first match is
+    /// known, so invalid UTF-8 after it isn't noticed.
+    ///
+    /// returns: the first match, if any, counted in bytes of `utf8`, or the
+    /// first decoding error read before it was known
+    pub fn find_utf8(
+        &self,
+        utf8: &[u8],
+    ) -> Result<Option<Match>, Utf8DecodeError> {
+        let mut error = None;
+        let string = crate::utf8::decode_utf8_iter(utf8)
+            .map_while(|c| c.map_err(|e| error = Some(e)).ok());
+        let found = self
+            .find_with_steps(
+                &mut self.find_scratch(),
+                string,
+                usize::MAX,
+                true,
+                &mut |_, _| {},
+            )
+            .expect("matching should take at most one step per codepoint")
+            .ok();
+        if let Some(error) = error {
+            return Err(error);
+        }
+        let Some((start, len)) = found else {
+            return Ok(None);
+        };
+
+        // the input is valid up to the end of the match, so the length of
+        // every codepoint until then can be read off its leading byte
+        let mut offset = 0;
+        let mut skip_codepoints = |count: usize| {
+            for _ in 0..count {
+                offset += match utf8[offset] {
+                    0x00..0x80 => 1,
+                    0x80..0xe0 => 2,
+                    0xe0..0xf0 => 3,
+                    _ => 4,
+                };
+            }
+            offset
         };
+        let byte_start = skip_codepoints(start);
+        let byte_end = skip_codepoints(len);
+        Ok(Some(Match {
+            start: byte_start,
+            len: byte_end - byte_start,
+        }))
+    }
+
+    /// returns: the starting index and length of the first match, if any,
+    /// widened to the nearest grapheme cluster boundaries so that it doesn't
+    /// split combining sequences
+    #[cfg(feature = "grapheme")]
+    pub fn find_grapheme_bounds(
+        &self,
+        string: &[UnicodeCodepoint],
+    ) -> Option<(usize, usize)> {
+        use unicode_segmentation::UnicodeSegmentation;
 
-        let mut graph = Graph::new();
-        let start_node = graph.get_initial_node();
-        let final_node = graph.add_node();
-        graph.set_final(final_node);
+        let (start, len) = self.find(string)?;
+        let end = start + len;
 
-        for a in regex.root.node.alts.nodes {
-            add_alt(&mut graph, start_node, final_node, a)
-                .map_err(RegexError::Utf8DecodeError)?;
+        // codepoint indices of the cluster boundaries, including both ends
+        let mut bounds = vec![0];
+        for grapheme in crate::utf8::encode_utf8_string(string).graphemes(true)
+        {
+            let last = *bounds.last().unwrap();
+            bounds.push(last + grapheme.chars().count());
         }
 
+        let start = bounds.iter().rev().find(|b| **b <= start).unwrap();
+        let end = bounds.iter().find(|b| **b >= end).unwrap();
+        Some((*start, end - start))
+    }
+
+    /// returns: all matches in `s`, see [`Regex::find_all`], counted in bytes
+    /// of `s`; every match starts and ends on a `char` boundary, so its
+    /// [`Match::range`] slices `s`
+    pub fn find_all_str(&self, s: &str) -> Vec<Match> {
+        let string: Vec<_> = s.chars().map(UnicodeCodepoint::from).collect();
+        // the byte offset of every codepoint index, including the end
+        let offsets: Vec<_> = s
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain([s.len()])
+            .collect();
+        self.find_all(&string)
+            .into_iter()
+            .map(|(start, len)| Match {
+                start: offsets[start],
+                len: offsets[start + len] - offsets[start],
+            })
+            .collect()
+    }
+}
+
+impl<T: Ord + Clone> GenericRegex<T> {
+    /// Compiles `graph` to matrices, after removing its epsilon edges and
+    /// the states no match goes through
+    fn from_graphs(
+        mut graph: Graph<T>,
+        capture_graph: Option<Graph<T>>,
+        group_count: usize,
+        group_names: BTreeMap<String, usize>,
+        options: &RegexOptions,
+    ) -> Result<GenericRegex<T>, RegexError> {
         graph.collapse_epsilons();
+        graph.remove_unreachable();
+
+        let CompiledGraph {
+            token_matrices,
+            start_anchor,
+            end_anchor,
+            final_nodes,
+        } = graph.compile(options.max_states, options.max_matrix_entries)?;
 
-        let (token_matrices, final_nodes) = graph.compile();
+        // a regex is start-anchored if every way out of the start state is a
+        // `^`, and end-anchored if every way into a final state is a `$`
+        let start_is_final = final_nodes.get(0);
+        let anchored_start = !start_is_final
+            && start_anchor.as_ref().is_some_and(|m| m.col(0).any(|v| v))
+            && token_matrices
+                .values()
+                .map(|m| &**m)
+                .chain(&end_anchor)
+                .all(|m| m.col(0).all(|v| !v));
+        let anchored_end = !start_is_final
+            && end_anchor.is_some()
+            && token_matrices
+                .values()
+                .map(|m| &**m)
+                .chain(&start_anchor)
+                .all(|m| {
+                    final_nodes
+                        .enumerate_iter()
+                        .filter(|(_, is_final)| **is_final)
+                        .all(|(i, _)| m.row(i).all(|v| !v))
+                });
 
-        Ok(Regex {
+        let mut regex = GenericRegex {
             token_matrices,
+            start_anchor,
+            end_anchor,
+            live_nodes: BitVector::new(final_nodes.size),
             final_nodes,
-        })
+            anchored_start,
+            anchored_end,
+            capture_graph,
+            group_count,
+            group_names,
+        };
+        regex.live_nodes = regex.find_live_nodes();
+        Ok(regex)
+    }
+
+    /// Compiles a pattern that's already split into its parts, with the
+    /// limits of [`RegexOptions::default`]. Named groups and case
+    /// insensitivity only exist in the text syntax, so they aren't available.
+    ///
+    /// returns: the regex matching `pattern`, or an error if it's too large
+    pub fn from_pattern(
+        pattern: &Pattern<T>,
+    ) -> Result<GenericRegex<T>, RegexError> {
+        GenericRegex::from_pattern_with_options(
+            pattern,
+            &RegexOptions::default(),
+        )
+    }
+
+    /// Like [`GenericRegex::from_pattern`], but with the limits of `options`.
+    /// [`RegexOptions::case_insensitive`] only applies to parsed patterns, so
+    /// it's ignored here.
+    pub fn from_pattern_with_options(
+        pattern: &Pattern<T>,
+        options: &RegexOptions,
+    ) -> Result<GenericRegex<T>, RegexError> {
+        pattern::compile(pattern, options)
+    }
+
+    /// returns: whether the regex matches the empty string; see
+    /// [`Regex::is_empty_pattern`] for whether that's all it matches
+    pub fn matches_empty(&self) -> bool {
+        self.test(&[])
+    }
+
+    /// returns: whether the regex matches the empty string and nothing else,
+    /// anywhere, like the empty pattern. Unlike [`Regex::matches_empty`],
+    /// this is false for `a*`, which matches more, and for `^` or `$`, which
+    /// only match in some places.
+    pub fn is_empty_pattern(&self) -> bool {
+        self.token_matrices.is_empty()
+            && self.start_anchor.is_none()
+            && self.end_anchor.is_none()
+            && self.final_nodes.get(0)
+    }
+
+    /// returns: whether every match has to start at the start of the input,
+    /// because every way out of the start state is a `^`
+    pub fn is_anchored_start(&self) -> bool {
+        self.anchored_start
+    }
+
+    /// returns: whether every match has to end at the end of the input,
+    /// because every way into a final state is a `$`
+    pub fn is_anchored_end(&self) -> bool {
+        self.anchored_end
+    }
+
+    /// returns: the indices of the accepting states, in order, numbered as in
+    /// [`Regex::export`]
+    pub fn final_states(&self) -> Vec<usize> {
+        self.final_nodes
+            .enumerate_iter()
+            .filter_map(|(i, value)| value.then_some(i))
+            .collect()
+    }
+
+    /// returns: every codepoint that can be part of a match, in order; input
+    /// containing any other codepoint can be skipped over
+    pub fn alphabet(&self) -> impl Iterator<Item = T> + '_ {
+        self.token_matrices.keys().cloned()
     }
 
     /// returns: whether the entire string matches the regex
-    pub fn test(&self, string: &[UnicodeCodepoint]) -> bool {
+    pub fn test(&self, string: &[T]) -> bool {
+        self.test_iter(string.iter().cloned())
+    }
+
+    fn test_iter(&self, string: impl IntoIterator<Item = T>) -> bool {
+        let mut string = string.into_iter().peekable();
         let mut accumulator = BitVector::new(self.final_nodes.size);
         // start node
         accumulator.set(0, true);
 
         let mut temp = BitVector::new(accumulator.size);
+        let at_end = string.peek().is_none();
+        self.follow_anchors(&mut accumulator, &mut temp, true, at_end);
 
-        for token in string {
-            let Some(matrix) = self.token_matrices.get(token) else {
+        while let Some(token) = string.next() {
+            let Some(matrix) = self.token_matrices.get(&token) else {
                 return false;
             };
             BitVector::mult(matrix, &accumulator, &mut temp);
-            std::mem::swap(&mut accumulator, &mut temp);
+            core::mem::swap(&mut accumulator, &mut temp);
+            accumulator.and_assign(&self.live_nodes);
+            // no state is left, so the rest of the string can't be matched
+            if accumulator.is_empty() {
+                return false;
+            }
+            let at_end = string.peek().is_none();
+            self.follow_anchors(&mut accumulator, &mut temp, false, at_end);
         }
 
         BitVector::dot(&accumulator, &self.final_nodes)
     }
 
-    /// returns: the starting index and length of the first match, if any
-    pub fn find(&self, string: &[UnicodeCodepoint]) -> Option<(usize, usize)> {
-        let mut accumulator = NfaVector::new(self.final_nodes.size);
-        let mut temp = NfaVector::new(accumulator.size);
+    /// returns: the states of the automaton before reading anything, for
+    /// driving it by hand with [`Regex::step`] and [`Regex::is_accepting`]
+    ///
+    /// Reading the string `s` one codepoint at a time with `step`, starting
+    /// from this, and then calling `is_accepting` gives the same answer as
+    /// [`Regex::test`], except for patterns that need a `^` after a `$`, like
+    /// `$^`, which the stepped states don't follow.
+    pub fn initial_state(&self) -> State {
+        let mut state = BitVector::new(self.final_nodes.size);
+        // start node
+        state.set(0, true);
+        let mut temp = BitVector::new(state.size);
+        self.follow_anchors(&mut state, &mut temp, true, false);
+        State(state)
+    }
 
-        // special case for initial final node
-        accumulator.set(0, Some(0));
-        if NfaVector::dot(&accumulator, &self.final_nodes).is_some() {
-            return Some((0, 0));
+    /// Sets `out` to the states reached from `state` by reading `token`; no
+    /// state is set if `token` isn't in the pattern
+    pub fn step(&self, state: &State, token: T, out: &mut State) {
+        match self.token_matrices.get(&token) {
+            Some(matrix) => BitVector::mult(matrix, &state.0, &mut out.0),
+            None => {
+                assert_eq!(state.0.size, out.0.size);
+                out.0.reset();
+            }
         }
+    }
 
-        let mut earliest_match = None;
+    /// returns: whether the string read so far to reach `state` matches the
+    /// regex, if it ends there
+    pub fn is_accepting(&self, state: &State) -> bool {
+        let mut state = state.0.clone();
+        let mut temp = BitVector::new(state.size);
+        self.follow_anchors(&mut state, &mut temp, false, true);
+        BitVector::dot(&state, &self.final_nodes)
+    }
 
-        for (token, index) in string.iter().zip(0_usize..) {
-            if accumulator.get(0).is_none() {
-                accumulator.set(0, Some(index));
-            }
+    /// Like [`Regex::test`], but a codepoint of `string` can be read as any
+    /// token `t` of the regex for which `eq(t, codepoint)` holds, not only
+    /// an equal one, such as for ad hoc case folding.
+    ///
+    /// Every token of the regex is compared against every codepoint, instead
+    /// of the codepoint being looked up, so this is slower than
+    /// [`Regex::test`] by a factor of the size of the regex's alphabet.
+    pub fn test_by(&self, string: &[T], eq: impl Fn(T, T) -> bool) -> bool {
+        let mut accumulator = BitVector::new(self.final_nodes.size);
+        // start node
+        accumulator.set(0, true);
 
-            let Some(matrix) = self.token_matrices.get(token) else {
-                accumulator.reset();
-                continue;
-            };
-            NfaVector::mult(matrix, &accumulator, &mut temp);
-            std::mem::swap(&mut accumulator, &mut temp);
+        let mut temp = BitVector::new(accumulator.size);
+        let mut next = BitVector::new(accumulator.size);
+        self.follow_anchors(
+            &mut accumulator,
+            &mut temp,
+            true,
+            string.is_empty(),
+        );
 
-            if let Some(match_index) =
-                NfaVector::dot(&accumulator, &self.final_nodes)
-            {
-                let current_match =
-                    Some((match_index, index - match_index + 1));
-                if let Some((earliest_match_index, _)) = earliest_match {
-                    if match_index < earliest_match_index {
-                        earliest_match = current_match;
-                    }
-                } else {
-                    earliest_match = current_match;
+        for (codepoint, len) in string.iter().zip(1_usize..) {
+            next.reset();
+            let mut any_token = false;
+            for (token, matrix) in &self.token_matrices {
+                if eq(token.clone(), codepoint.clone()) {
+                    BitVector::mult(matrix, &accumulator, &mut temp);
+                    next.or_assign(&temp);
+                    any_token = true;
                 }
             }
+            if !any_token {
+                return false;
+            }
+            core::mem::swap(&mut accumulator, &mut next);
+            let at_end = len == string.len();
+            self.follow_anchors(&mut accumulator, &mut temp, false, at_end);
         }
-        earliest_match
-    }
 
-    /// returns: the starting index and length of all matches
-    pub fn find_all(&self, string: &[UnicodeCodepoint]) -> Vec<(usize, usize)> {
-        let mut matches = Vec::new();
+        BitVector::dot(&accumulator, &self.final_nodes)
+    }
 
-        let mut accumulator = NfaVector::new(self.final_nodes.size);
-        let mut temp = NfaVector::new(accumulator.size);
+    /// Like [`Regex::test`], for input that may be cut short, like a message
+    /// still being received. Reading stops as soon as nothing starting with
+    /// the input read so far can match.
+    ///
+    /// returns: whether the entire string matches the regex, and if not,
+    /// whether more input could make it match
+    pub fn test_partial(&self, string: &[T]) -> PartialMatch {
+        self.test_partial_iter(string.iter().cloned())
+    }
 
-        // special case for initial final node
-        accumulator.set(0, Some(0));
-        if NfaVector::dot(&accumulator, &self.final_nodes).is_some() {
-            matches.push((0, 0));
-        }
+    fn test_partial_iter(
+        &self,
+        string: impl IntoIterator<Item = T>,
+    ) -> PartialMatch {
+        let live_nodes = &self.live_nodes;
+        let mut accumulator = BitVector::new(self.final_nodes.size);
+        // start node
+        accumulator.set(0, true);
 
-        for (token, index) in string.iter().zip(0_usize..) {
-            accumulator.set(0, Some(index));
+        // more input may follow, so `$` is only followed once it's known not
+        // to
+        let mut temp = BitVector::new(accumulator.size);
+        self.follow_anchors(&mut accumulator, &mut temp, true, false);
+        let mut at_start = true;
 
-            let Some(matrix) = self.token_matrices.get(token) else {
-                accumulator.reset();
-                continue;
+        for token in string {
+            if !BitVector::dot(&accumulator, live_nodes) {
+                return PartialMatch::Rejected;
+            }
+            let Some(matrix) = self.token_matrices.get(&token) else {
+                return PartialMatch::Rejected;
             };
-            NfaVector::mult(matrix, &accumulator, &mut temp);
-            std::mem::swap(&mut accumulator, &mut temp);
+            BitVector::mult(matrix, &accumulator, &mut temp);
+            core::mem::swap(&mut accumulator, &mut temp);
+            self.follow_anchors(&mut accumulator, &mut temp, false, false);
+            at_start = false;
+        }
 
-            if let Some(start_index) =
-                NfaVector::dot(&accumulator, &self.final_nodes)
-            {
-                matches.push((start_index, index - start_index + 1));
-            }
+        let mut at_end = accumulator.clone();
+        self.follow_anchors(&mut at_end, &mut temp, at_start, true);
+        if BitVector::dot(&at_end, &self.final_nodes) {
+            PartialMatch::Complete
+        } else if BitVector::dot(&accumulator, live_nodes) {
+            PartialMatch::NeedsMore
+        } else {
+            PartialMatch::Rejected
         }
-        matches
     }
-}
 
-fn add_alt(
-    graph: &mut Graph,
-    start: NodeRef,
-    end: NodeRef,
-    alt: ConcatExpr,
-) -> Result<(), Utf8DecodeError> {
-    let mut prev = start;
-    for p in alt.parts.nodes {
-        let is_kleene = p.star.is_some();
-        let next = if is_kleene { prev } else { graph.add_node() };
-        match p.atom {
-            Atom::CharacterAtom(c) => {
-                let token = c.to_codepoint()?;
-                graph.connect(prev, next, token);
-            }
-            Atom::Capture { alt, .. } => {
-                for a in alt.alts.nodes {
-                    add_alt(graph, prev, next, a)?;
+    /// returns: the states from which a final state can be reached by reading
+    /// more input and then following `$` edges, see [`Regex::live_nodes`]
+    fn find_live_nodes(&self) -> BitVector {
+        let mut live_nodes = self.final_nodes.clone();
+        let mut temp = BitVector::new(live_nodes.size);
+        self.follow_anchors_back(&mut live_nodes, &mut temp, false, true);
+
+        // any token leads on, so only the union of the token matrices is
+        // searched, backwards, reading each of its rows at most once
+        let matrices: Vec<_> =
+            self.token_matrices.values().map(|m| &**m).collect();
+        if matrices.is_empty() {
+            return live_nodes;
+        }
+        let any_token = BitMatrix::or_all(&matrices);
+        let mut stack: Vec<usize> = live_nodes
+            .enumerate_iter()
+            .filter_map(|(i, live)| live.then_some(i))
+            .collect();
+        while let Some(to) = stack.pop() {
+            for (from, edge) in any_token.row(to).enumerate() {
+                if edge && !live_nodes.get(from) {
+                    live_nodes.set(from, true);
+                    stack.push(from);
                 }
             }
         }
-        prev = next;
+        live_nodes
+    }
+
+    /// returns: the length of the shortest prefix of `string` that matches the
+    /// regex, if any
+    pub fn shortest_match(&self, string: &[T]) -> Option<usize> {
+        self.shortest_match_at(string, 0)
     }
-    if prev != end {
-        graph.connect_epsilon(prev, end);
+
+    /// returns: whether a match starts exactly at index `start` of `string`,
+    /// without searching further on like [`Regex::find`]; a `^` only matches
+    /// if `start` is 0
+    ///
+    /// Panics if `start` is past the end of `string`.
+    pub fn is_match_at(&self, string: &[T], start: usize) -> bool {
+        self.shortest_match_at(string, start).is_some()
     }
-    Ok(())
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::utf8;
+    /// returns: the length of the shortest match starting at index `start` of
+    /// `string`, if any
+    fn shortest_match_at(&self, string: &[T], start: usize) -> Option<usize> {
+        let rest = &string[start..];
+        let mut accumulator = BitVector::new(self.final_nodes.size);
+        // start node
+        accumulator.set(0, true);
 
-    #[test]
-    fn regex_test() {
-        fn test(r: &str, s: &str) -> bool {
-            Regex::new(r.as_bytes())
-                .unwrap()
-                .test(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        let mut temp = BitVector::new(accumulator.size);
+        self.follow_anchors(
+            &mut accumulator,
+            &mut temp,
+            start == 0,
+            rest.is_empty(),
+        );
+        if BitVector::dot(&accumulator, &self.final_nodes) {
+            return Some(0);
         }
 
-        assert!(test("", ""));
-        assert!(!test("", "a"));
+        for (token, len) in rest.iter().zip(1_usize..) {
+            let matrix = self.token_matrices.get(token)?;
+            BitVector::mult(matrix, &accumulator, &mut temp);
+            core::mem::swap(&mut accumulator, &mut temp);
+            let at_end = len == rest.len();
+            self.follow_anchors(&mut accumulator, &mut temp, false, at_end);
 
-        assert!(!test("a", "ab"));
-        assert!(!test("ab", "a"));
+            if BitVector::dot(&accumulator, &self.final_nodes) {
+                return Some(len);
+            }
+        }
+        None
+    }
 
-        assert!(test("a(a(b|cd)*|ab)*c", "ac"));
-        assert!(test("a(a(b|cd)*|ab)*c", "aac"));
-        assert!(test("a(a(b|cd)*|ab)*c", "aabbbbabc"));
-        assert!(test("a(a(b|cd)*|ab)*c", "aabbabacdcdabc"));
+    /// returns: the length of the shortest match starting at index `start` of
+    /// `string` that isn't empty, if any
+    fn shortest_nonempty_match_at(
+        &self,
+        scratch: &mut FindScratch,
+        string: &[T],
+        start: usize,
+    ) -> Option<usize> {
+        let FindScratch { accumulator, temp } = scratch;
+        let rest = &string[start..];
+        accumulator.reset();
+        accumulator.set(0, Some(start));
+        self.follow_anchors_nfa(accumulator, temp, start == 0, rest.is_empty());
 
-        assert!(!test("a(a(b|cd)*|ab)*c", ""));
-        assert!(!test("a(a(b|cd)*|ab)*c", "a"));
-        assert!(!test("a(a(b|cd)*|ab)*c", "c"));
+        for (token, len) in rest.iter().zip(1_usize..) {
+            let matrix = self.token_matrices.get(token)?;
+            NfaVector::mult(matrix, accumulator, temp);
+            core::mem::swap(accumulator, temp);
+            accumulator.and_assign(&self.live_nodes);
+            let at_end = len == rest.len();
+            self.follow_anchors_nfa(accumulator, temp, false, at_end);
+
+            if NfaVector::dot(accumulator, &self.final_nodes).is_some() {
+                return Some(len);
+            }
+            accumulator.min_value()?;
+        }
+        None
     }
 
-    #[test]
-    fn regex_find() {
-        fn find(r: &str, s: &str) -> Option<(usize, usize)> {
-            Regex::new(r.as_bytes())
-                .unwrap()
-                .find(&utf8::decode_utf8(s.as_bytes()).unwrap())
+    /// returns: the starting index and length of the first match, if any, see
+    /// [`Regex::leftmost_match`] for which match that is
+    pub fn find(&self, string: &[T]) -> Option<(usize, usize)> {
+        // anchored at both ends, the only possible match is all of `string`,
+        // which `test` checks without tracking where matches start
+        if self.anchored_start && self.anchored_end {
+            return self.test(string).then_some((0, string.len()));
         }
+        self.find_with_reason(string).ok()
+    }
 
-        assert_eq!(find("", ""), Some((0, 0)));
-        assert_eq!(find("", "a"), Some((0, 0)));
+    /// The match that [`Regex::find`] returns: of the matches starting at the
+    /// lowest index, the shortest one. Unlike a backtracking engine, which
+    /// returns the first alternative that matches, `ab|a` finds `a` in `ab`,
+    /// and `a*` finds the empty string at index 0 in `aa`.
+    ///
+    /// The string is read once from the start. Every state of the automaton
+    /// holds the lowest index that an attempt reaching it started at, and a
+    /// new attempt starts in the start state at every index until a match is
+    /// found. Reading a codepoint keeps the lowest start of the states leading
+    /// into each state, so the first time a final state is reached, it holds
+    /// the leftmost start that can match there, and the match is the shortest
+    /// from that start. Reading goes on only while an attempt that started
+    /// before the match is still active, since one of those finishing later
+    /// would start further left.
+    ///
+    /// returns: the match, if any
+    pub fn leftmost_match(&self, string: &[T]) -> Option<Match> {
+        self.find_match(string)
+    }
 
-        assert_eq!(find("a", "ab"), Some((0, 1)));
-        assert_eq!(find("ab", "a"), None);
+    /// returns: the first match, if any; the same as [`Regex::find`]
+    pub fn find_match(&self, string: &[T]) -> Option<Match> {
+        self.find(string).map(|(start, len)| Match { start, len })
+    }
 
-        assert_eq!(find("a(a(b|cd)*|ab)*c", "ac"), Some((0, 2)));
+    /// returns: the first match, if any, whose [`Match::range`] slices it out
+    /// of `string`; the same as [`Regex::find_match`]
+    pub fn find_range(&self, string: &[T]) -> Option<Match> {
+        self.find_match(string)
+    }
+
+    /// returns: the starting index and length of the first match, or the
+    /// reason no match was found
+    pub fn find_with_reason(
+        &self,
+        string: &[T],
+    ) -> Result<(usize, usize), FindFailure> {
+        self.find_with_steps(
+            &mut self.find_scratch(),
+            string.iter().cloned(),
+            usize::MAX,
+            true,
+            &mut |_, _| {},
+        )
+        .expect("matching should take at most one step per codepoint")
+    }
+
+    /// Like [`Regex::find`], but gives up once matching has taken `max_steps`
+    /// steps, where a step is advancing the automaton over one codepoint. A
+    /// search takes at most one step per codepoint of `string`.
+    ///
+    /// returns: the first match, if any
+    pub fn find_with_budget(
+        &self,
+        string: &[T],
+        max_steps: usize,
+    ) -> Result<Option<Match>, BudgetExceeded> {
+        Ok(self
+            .find_with_steps(
+                &mut self.find_scratch(),
+                string.iter().cloned(),
+                max_steps,
+                true,
+                &mut |_, _| {},
+            )?
+            .ok()
+            .map(|(start, len)| Match { start, len }))
+    }
+
+    /// Like [`Regex::find`], but calls `on_step` after every codepoint read
+    /// with its index and the active states: for each state, numbered as in
+    /// [`Regex::export`], the earliest start of an attempt that is in it, or
+    /// `None` if no attempt is. Like [`Regex::find_chars`], reading stops
+    /// once the first match is known.
+    ///
+    /// returns: the first match, if any
+    pub fn find_traced(
+        &self,
+        string: &[T],
+        mut on_step: impl FnMut(usize, &[Option<usize>]),
+    ) -> Option<Match> {
+        self.find_with_steps(
+            &mut self.find_scratch(),
+            string.iter().cloned(),
+            usize::MAX,
+            true,
+            &mut on_step,
+        )
+        .expect("matching should take at most one step per codepoint")
+        .ok()
+        .map(|(start, len)| Match { start, len })
+    }
+
+    /// `at_start` is whether `string` starts at the start of the input, where
+    /// `^` holds, and `on_step` is called after each codepoint read, see
+    /// [`Regex::find_traced`]
+    fn find_with_steps(
+        &self,
+        scratch: &mut FindScratch,
+        string: impl IntoIterator<Item = T>,
+        max_steps: usize,
+        at_start: bool,
+        on_step: &mut impl FnMut(usize, &[Option<usize>]),
+    ) -> Result<Result<(usize, usize), FindFailure>, BudgetExceeded> {
+        if !at_start && self.anchored_start {
+            return Ok(Err(FindFailure::Rejected));
+        }
+        let mut string = string.into_iter().peekable();
+        let mut steps = 0;
+        let FindScratch { accumulator, temp } = scratch;
+        accumulator.reset();
+
+        // special case for initial final node; the input is only looked ahead
+        // at for `$`, so that no more of it is read than needed
+        accumulator.set(0, Some(0));
+        let at_end = self.end_anchor.is_some() && string.peek().is_none();
+        self.follow_anchors_nfa(accumulator, temp, at_start, at_end);
+        if NfaVector::dot(accumulator, &self.final_nodes).is_some() {
+            return Ok(Ok((0, 0)));
+        }
+
+        let mut earliest_match: Option<(usize, usize)> = None;
+        let mut len = 0;
+
+        for index in 0_usize.. {
+            let Some(token) = string.next() else {
+                break;
+            };
+            len = index + 1;
+
+            // attempts starting after a found match can't beat it, and a
+            // start-anchored regex can only match from the start
+            if earliest_match.is_none()
+                && accumulator.get(0).is_none()
+                && !self.anchored_start
+            {
+                accumulator.set(0, Some(index));
+            }
+
+            let Some(matrix) = self.token_matrices.get(&token) else {
+                accumulator.reset();
+                on_step(index, accumulator.as_slice());
+                if earliest_match.is_some() || self.anchored_start {
+                    break;
+                }
+                continue;
+            };
+            if steps == max_steps {
+                return Err(BudgetExceeded { max_steps });
+            }
+            steps += 1;
+            NfaVector::mult(matrix, accumulator, temp);
+            core::mem::swap(accumulator, temp);
+            accumulator.and_assign(&self.live_nodes);
+            let at_end = self.end_anchor.is_some() && string.peek().is_none();
+            self.follow_anchors_nfa(accumulator, temp, false, at_end);
+            on_step(index, accumulator.as_slice());
+
+            if let Some(match_index) =
+                NfaVector::dot(accumulator, &self.final_nodes)
+            {
+                let current_match =
+                    Some((match_index, index - match_index + 1));
+                if let Some((earliest_match_index, _)) = earliest_match {
+                    if match_index < earliest_match_index {
+                        earliest_match = current_match;
+                    }
+                } else {
+                    earliest_match = current_match;
+                }
+            }
+
+            if let Some((earliest_match_index, _)) = earliest_match {
+                // only attempts that started earlier can still beat the match
+                if accumulator
+                    .min_value()
+                    .is_none_or(|start| start >= earliest_match_index)
+                {
+                    break;
+                }
+            } else if self.anchored_start && accumulator.min_value().is_none() {
+                break;
+            }
+        }
+
+        let attempt_active = accumulator.min_value().is_some();
+
+        // an empty match at the end of the input, like `$` on its own; the
+        // loop above only stops early without a match if start-anchored
+        if earliest_match.is_none()
+            && !self.anchored_start
+            && self.end_anchor.is_some()
+            && len > 0
+        {
+            if accumulator.get(0).is_none() {
+                accumulator.set(0, Some(len));
+            }
+            self.follow_anchors_nfa(accumulator, temp, false, true);
+            if let Some(match_index) =
+                NfaVector::dot(accumulator, &self.final_nodes)
+            {
+                earliest_match = Some((match_index, len - match_index));
+            }
+        }
+
+        Ok(match earliest_match {
+            Some(m) => Ok(m),
+            None if attempt_active => Err(FindFailure::EndOfInput),
+            None => Err(FindFailure::Rejected),
+        })
+    }
+
+    /// returns: the starting index and length of all matches that don't
+    /// overlap, from left to right
+    ///
+    /// Each match starts as early as possible where the previous one ended or
+    /// later. Of the matches that start there, the shortest non-empty one is
+    /// taken, so unlike [`Regex::find`], this finds `(0, 1)` for `a*` in
+    /// `aba` rather than `(0, 0)`. An empty match is only taken if there's no
+    /// other, and is stepped over before searching again, so there's one at
+    /// every index from `0` to `string.len()` where nothing else starts: for
+    /// `a*` in `aba`, the matches are `(0, 1)`, `(1, 0)`, `(2, 1)` and
+    /// `(3, 0)`. The methods that replace or split at matches, and
+    /// [`Regex::captures_find_iter`], use the same matches.
+    ///
+    /// Changed in 0.2.0: this used to return, for every index, the match
+    /// ending there with the earliest start, so matches could overlap, and
+    /// empty matches were only found at the start and end of `string`.
+    pub fn find_all(&self, string: &[T]) -> Vec<(usize, usize)> {
+        self.matches(string).collect()
+    }
+
+    /// returns: of the matches that end at or before `end`, the one that ends
+    /// last, with the earliest start of those ending there, if any; an `end`
+    /// past the end of `string` is the same as its length
+    ///
+    /// Unlike for [`Regex::find_all`], the match can overlap earlier ones,
+    /// and an empty match only counts at the start of `string`, or at its end
+    /// after a `$`. Only the string up to `end` is read, but a `$` still only
+    /// matches at the end of all of `string`.
+    pub fn rfind_at(&self, string: &[T], end: usize) -> Option<Match> {
+        let mut last = None;
+        self.each_match(string, end.min(string.len()), |start, len| {
+            last = Some(Match { start, len });
+        });
+        last
+    }
+
+    /// Calls `on_match` with the starting index and length of the match that
+    /// ends at each index up to `end` with the earliest start, in order of
+    /// their ends, see [`Regex::rfind_at`]
+    fn each_match(
+        &self,
+        string: &[T],
+        end: usize,
+        mut on_match: impl FnMut(usize, usize),
+    ) {
+        // only the empty match at the start is reported, like for `a*`, so
+        // there's no need to read the string
+        if self.is_empty_pattern() {
+            on_match(0, 0);
+            return;
+        }
+
+        let mut accumulator = NfaVector::new(self.final_nodes.size);
+        let mut temp = NfaVector::new(accumulator.size);
+
+        // special case for initial final node
+        accumulator.set(0, Some(0));
+        self.follow_anchors_nfa(
+            &mut accumulator,
+            &mut temp,
+            true,
+            string.is_empty(),
+        );
+        if NfaVector::dot(&accumulator, &self.final_nodes).is_some() {
+            on_match(0, 0);
+        }
+
+        for (token, index) in string[..end].iter().zip(0_usize..) {
+            if !self.anchored_start {
+                accumulator.set(0, Some(index));
+            }
+
+            let Some(matrix) = self.token_matrices.get(token) else {
+                accumulator.reset();
+                if self.anchored_start {
+                    break;
+                }
+                continue;
+            };
+            NfaVector::mult(matrix, &accumulator, &mut temp);
+            core::mem::swap(&mut accumulator, &mut temp);
+            let at_end = index + 1 == string.len();
+            self.follow_anchors_nfa(&mut accumulator, &mut temp, false, at_end);
+
+            if let Some(start_index) =
+                NfaVector::dot(&accumulator, &self.final_nodes)
+            {
+                on_match(start_index, index - start_index + 1);
+            }
+        }
+
+        // an empty match at the end of the input, like `$` on its own
+        if !string.is_empty()
+            && end == string.len()
+            && self.end_anchor.is_some()
+        {
+            let len = string.len();
+            if accumulator.get(0).is_none() && !self.anchored_start {
+                accumulator.set(0, Some(len));
+            }
+            self.follow_anchors_nfa(&mut accumulator, &mut temp, false, true);
+            if NfaVector::dot(&accumulator, &self.final_nodes) == Some(len) {
+                on_match(len, 0);
+            }
+        }
+    }
+
+    /// returns: the number of groups in the pattern, not counting the whole
+    /// match
+    pub fn group_count(&self) -> usize {
+        self.group_count
+    }
+
+    /// returns: the first match, if any, followed by what each group of the
+    /// pattern matched in it, as starting indices and lengths; groups that
+    /// didn't take part in the match are `None`, and groups that matched
+    /// more than once give their last match
+    ///
+    /// Where a match can be split up between the groups in more than one
+    /// way, alternatives to the left are preferred, and `*` matches as much
+    /// as it can.
+    pub fn captures(
+        &self,
+        string: &[T],
+    ) -> Option<Vec<Option<(usize, usize)>>> {
+        let (start, len) = self.find(string)?;
+        let scratch = &mut CaptureScratch::default();
+        Some(self.captures_at(scratch, string, start, len))
+    }
+
+    /// returns: what each group matched, like [`Regex::captures`], if the
+    /// entire string matches the regex, see [`Regex::test`]
+    pub fn captures_full(
+        &self,
+        string: &[T],
+    ) -> Option<Vec<Option<(usize, usize)>>> {
+        if !self.test(string) {
+            return None;
+        }
+        let scratch = &mut CaptureScratch::default();
+        Some(self.captures_at(scratch, string, 0, string.len()))
+    }
+
+    /// Like [`Regex::captures`], for the groups named with `(?P<name>...)`
+    ///
+    /// returns: what each named group that took part in the first match
+    /// matched, by name, if there is a match
+    pub fn captures_named(
+        &self,
+        string: &[T],
+    ) -> Option<BTreeMap<String, (usize, usize)>> {
+        let groups = self.captures(string)?;
+        Some(
+            self.group_names
+                .iter()
+                .filter_map(|(name, group)| {
+                    Some((name.clone(), groups[*group]?))
+                })
+                .collect(),
+        )
+    }
+
+    /// returns: the first match starting at `offset` or later, if any
+    fn find_at(
+        &self,
+        scratch: &mut FindScratch,
+        string: &[T],
+        offset: usize,
+    ) -> Option<(usize, usize)> {
+        let rest = string[offset..].iter().cloned();
+        self.find_with_steps(
+            scratch,
+            rest,
+            usize::MAX,
+            offset == 0,
+            &mut |_, _| {},
+        )
+        .expect("matching should take at most one step per codepoint")
+        .ok()
+        .map(|(start, len)| (offset + start, len))
+    }
+
+    /// returns: the vectors for [`Regex::find_with_steps`] to work in
+    fn find_scratch(&self) -> FindScratch {
+        FindScratch {
+            accumulator: NfaVector::new(self.final_nodes.size),
+            temp: NfaVector::new(self.final_nodes.size),
+        }
+    }
+
+    /// returns: the groups of the match `string[start..start + len]`, see
+    /// [`Regex::captures`]
+    ///
+    /// The capture graph should accept every match of the matrices. If it
+    /// doesn't, that's a bug, and in release builds only the whole match is
+    /// reported, with every other group `None`.
+    fn captures_at(
+        &self,
+        scratch: &mut CaptureScratch,
+        string: &[T],
+        start: usize,
+        len: usize,
+    ) -> Vec<Option<(usize, usize)>> {
+        let mut groups = vec![None; self.group_count + 1];
+        groups[0] = Some((start, len));
+        let Some(graph) = &self.capture_graph else {
+            return groups;
+        };
+        let slot_count = 2 * groups.len();
+        let slots =
+            graph.captures(scratch, string, start, start + len, slot_count);
+        debug_assert!(
+            slots.is_some(),
+            "the capture graph should accept the match {start}..{}",
+            start + len
+        );
+        let Some(slots) = slots else {
+            return groups;
+        };
+        for (group, slots) in groups.iter_mut().zip(slots.chunks(2)).skip(1) {
+            if let [Some(group_start), Some(group_end)] = *slots {
+                *group = Some((group_start, group_end - group_start));
+            }
+        }
+        groups
+    }
+
+    /// returns: for every index of `string`, whether a match starts there;
+    /// unlike [`Regex::find_all`], this includes every start of overlapping
+    /// and nested matches, and empty matches anywhere
+    ///
+    /// An empty match at the very end of `string` has no index, so it isn't
+    /// included.
+    pub fn match_mask(&self, string: &[T]) -> Vec<bool> {
+        // `accumulator` holds the states from which a final state can be
+        // reached by reading some prefix of the rest of the string, going
+        // backwards from its end
+        let mut accumulator = self.final_nodes.clone();
+        let mut temp = BitVector::new(accumulator.size);
+        self.follow_anchors_back(
+            &mut accumulator,
+            &mut temp,
+            string.is_empty(),
+            true,
+        );
+
+        let mut mask = vec![false; string.len()];
+        for (index, token) in string.iter().enumerate().rev() {
+            match self.token_matrices.get(token) {
+                Some(matrix) => {
+                    BitVector::mult_transposed(matrix, &accumulator, &mut temp);
+                    core::mem::swap(&mut accumulator, &mut temp);
+                }
+                None => accumulator.reset(),
+            }
+            // a match can also end here
+            accumulator.or_assign(&self.final_nodes);
+            self.follow_anchors_back(
+                &mut accumulator,
+                &mut temp,
+                index == 0,
+                false,
+            );
+            mask[index] = accumulator.get(0);
+        }
+        mask
+    }
+
+    /// Finds the matches for every start in one backwards pass over `string`,
+    /// like [`Regex::match_mask`]
+    ///
+    /// returns: for every index of `string`, the end index of the shortest
+    /// match starting there, if any; [`Regex::find`] is the first match of
+    /// these. An empty match at the very end of `string` has no index, so it
+    /// isn't included.
+    pub fn earliest_matches(&self, string: &[T]) -> Vec<Option<usize>> {
+        // `accumulator` holds, for every state, the earliest end of a match
+        // from it reading the rest of the string, going backwards from its
+        // end
+        let mut accumulator = NfaVector::new(self.final_nodes.size);
+        let mut temp = NfaVector::new(accumulator.size);
+        let set_final = |accumulator: &mut NfaVector, index: usize| {
+            for (i, is_final) in self.final_nodes.enumerate_iter() {
+                if *is_final {
+                    accumulator.set(i, Some(index));
+                }
+            }
+        };
+        set_final(&mut accumulator, string.len());
+        self.follow_anchors_back_nfa(
+            &mut accumulator,
+            &mut temp,
+            string.is_empty(),
+            true,
+        );
+
+        let mut ends = vec![None; string.len()];
+        for (index, token) in string.iter().enumerate().rev() {
+            match self.token_matrices.get(token) {
+                Some(matrix) => {
+                    NfaVector::mult_transposed(matrix, &accumulator, &mut temp);
+                    core::mem::swap(&mut accumulator, &mut temp);
+                }
+                None => accumulator.reset(),
+            }
+            // a match can also end here, which is earlier than any other
+            set_final(&mut accumulator, index);
+            self.follow_anchors_back_nfa(
+                &mut accumulator,
+                &mut temp,
+                index == 0,
+                false,
+            );
+            ends[index] = accumulator.get(0);
+        }
+        ends
+    }
+
+    /// returns: for every index of `string`, the end of the match
+    /// [`Regex::find_all`] takes if it searches from there: the shortest
+    /// non-empty match starting there, or else an empty one, if any
+    #[cfg(feature = "rayon")]
+    fn find_all_ends(&self, string: &[T]) -> Vec<Option<usize>> {
+        // like for `earliest_matches`, but the earliest end of a non-empty
+        // match is looked at before a match can end at the index itself
+        let mut accumulator = NfaVector::new(self.final_nodes.size);
+        let mut temp = NfaVector::new(accumulator.size);
+        let set_final = |accumulator: &mut NfaVector, index: usize| {
+            for (i, is_final) in self.final_nodes.enumerate_iter() {
+                if *is_final {
+                    accumulator.set(i, Some(index));
+                }
+            }
+        };
+        set_final(&mut accumulator, string.len());
+        self.follow_anchors_back_nfa(
+            &mut accumulator,
+            &mut temp,
+            string.is_empty(),
+            true,
+        );
+
+        let mut ends = vec![None; string.len()];
+        for (index, token) in string.iter().enumerate().rev() {
+            match self.token_matrices.get(token) {
+                Some(matrix) => {
+                    NfaVector::mult_transposed(matrix, &accumulator, &mut temp);
+                    core::mem::swap(&mut accumulator, &mut temp);
+                }
+                None => accumulator.reset(),
+            }
+            let at_start = index == 0;
+            self.follow_anchors_back_nfa(
+                &mut accumulator,
+                &mut temp,
+                at_start,
+                false,
+            );
+            let nonempty = accumulator.get(0);
+            set_final(&mut accumulator, index);
+            self.follow_anchors_back_nfa(
+                &mut accumulator,
+                &mut temp,
+                at_start,
+                false,
+            );
+            let empty = accumulator.get(0) == Some(index);
+            ends[index] = nonempty.or(empty.then_some(index));
+        }
+        ends
+    }
+
+    /// Runs the automaton backwards from the end of `string`, so only as much
+    /// of it is read as the longest suffix that could still match
+    ///
+    /// returns: whether some suffix of `string`, possibly empty, matches the
+    /// regex
+    pub fn test_suffix(&self, string: &[T]) -> bool {
+        // `accumulator` holds the states from which a final state can be
+        // reached by reading the suffix read so far
+        let mut accumulator = self.final_nodes.clone();
+        let mut temp = BitVector::new(accumulator.size);
+        self.follow_anchors_back(
+            &mut accumulator,
+            &mut temp,
+            string.is_empty(),
+            true,
+        );
+        if accumulator.get(0) {
+            return true;
+        }
+
+        for (index, token) in string.iter().enumerate().rev() {
+            let Some(matrix) = self.token_matrices.get(token) else {
+                return false;
+            };
+            BitVector::mult_transposed(matrix, &accumulator, &mut temp);
+            core::mem::swap(&mut accumulator, &mut temp);
+            self.follow_anchors_back(
+                &mut accumulator,
+                &mut temp,
+                index == 0,
+                false,
+            );
+            if accumulator.get(0) {
+                return true;
+            }
+            if accumulator.enumerate_iter().all(|(_, value)| !value) {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// returns: the same matches as [`Regex::find_all`], computed by matching
+    /// overlapping chunks of `string` in parallel
+    ///
+    /// Chunks need to overlap by the length of the longest possible match, so
+    /// this falls back to [`Regex::find_all`] if matches can be arbitrarily
+    /// long (the automaton has a reachable cycle) or `string` is short.
+    #[cfg(feature = "rayon")]
+    pub fn find_all_parallel(&self, string: &[T]) -> Vec<(usize, usize)>
+    where
+        T: Sync,
+    {
+        use rayon::prelude::*;
+
+        const CHUNK_SIZE: usize = 1 << 14;
+
+        // chunks other than the first don't start or end where the input does
+        if self.start_anchor.is_some() || self.end_anchor.is_some() {
+            return self.find_all(string);
+        }
+
+        let max_len = match self.max_match_len() {
+            Some(max_len) if string.len() > CHUNK_SIZE => max_len,
+            _ => return self.find_all(string),
+        };
+
+        // the end of the match taken at each index only depends on the next
+        // `max_len` codepoints
+        let chunk_starts: Vec<_> =
+            (0..string.len()).step_by(CHUNK_SIZE).collect();
+        let chunks: Vec<_> = chunk_starts
+            .into_par_iter()
+            .map(|chunk_start| {
+                let chunk_end = (chunk_start + CHUNK_SIZE).min(string.len());
+                let read_end = (chunk_end + max_len).min(string.len());
+                let mut ends =
+                    self.find_all_ends(&string[chunk_start..read_end]);
+                ends.truncate(chunk_end - chunk_start);
+                ends.into_iter()
+                    .map(|end| end.map(|end| chunk_start + end))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let ends = chunks.concat();
+
+        // then the same steps through them as `find_all` takes
+        let mut matches = Vec::new();
+        let mut index = 0;
+        while index <= string.len() {
+            let end = match ends.get(index) {
+                Some(end) => *end,
+                // without anchors, an empty match at the end of the string
+                // is the same as anywhere else
+                None => self.final_nodes.get(0).then_some(index),
+            };
+            match end {
+                Some(end) => {
+                    matches.push((index, end - index));
+                    index = if end == index { index + 1 } else { end };
+                }
+                None => index += 1,
+            }
+        }
+        matches
+    }
+
+    /// Activates every state reachable from an active one through `^` edges if
+    /// `at_start`, and through `$` edges if `at_end`
+    fn follow_anchors(
+        &self,
+        accumulator: &mut BitVector,
+        temp: &mut BitVector,
+        at_start: bool,
+        at_end: bool,
+    ) {
+        let anchors = [
+            self.start_anchor.as_ref().filter(|_| at_start),
+            self.end_anchor.as_ref().filter(|_| at_end),
+        ];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for matrix in anchors.into_iter().flatten() {
+                BitVector::mult(matrix, accumulator, temp);
+                changed |= accumulator.or_assign(temp);
+            }
+        }
+    }
+
+    /// Like [`Regex::follow_anchors`], but follows the anchors backwards
+    fn follow_anchors_back(
+        &self,
+        accumulator: &mut BitVector,
+        temp: &mut BitVector,
+        at_start: bool,
+        at_end: bool,
+    ) {
+        let anchors = [
+            self.start_anchor.as_ref().filter(|_| at_start),
+            self.end_anchor.as_ref().filter(|_| at_end),
+        ];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for matrix in anchors.into_iter().flatten() {
+                BitVector::mult_transposed(matrix, accumulator, temp);
+                changed |= accumulator.or_assign(temp);
+            }
+        }
+    }
+
+    /// Like [`Regex::follow_anchors_nfa`], but follows the anchors backwards
+    fn follow_anchors_back_nfa(
+        &self,
+        accumulator: &mut NfaVector,
+        temp: &mut NfaVector,
+        at_start: bool,
+        at_end: bool,
+    ) {
+        let anchors = [
+            self.start_anchor.as_ref().filter(|_| at_start),
+            self.end_anchor.as_ref().filter(|_| at_end),
+        ];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for matrix in anchors.into_iter().flatten() {
+                NfaVector::mult_transposed(matrix, accumulator, temp);
+                changed |= accumulator.min_assign(temp);
+            }
+        }
+    }
+
+    /// Like [`Regex::follow_anchors`], but keeps the earliest start index of
+    /// every state
+    fn follow_anchors_nfa(
+        &self,
+        accumulator: &mut NfaVector,
+        temp: &mut NfaVector,
+        at_start: bool,
+        at_end: bool,
+    ) {
+        let anchors = [
+            self.start_anchor.as_ref().filter(|_| at_start),
+            self.end_anchor.as_ref().filter(|_| at_end),
+        ];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for matrix in anchors.into_iter().flatten() {
+                NfaVector::mult(matrix, accumulator, temp);
+                changed |= accumulator.min_assign(temp);
+            }
+        }
+    }
+
+    /// returns: the length of the longest possible match, or `None` if it's
+    /// unbounded
+    #[cfg(feature = "rayon")]
+    fn max_match_len(&self) -> Option<usize> {
+        #[derive(Copy, Clone)]
+        enum Mark {
+            Unvisited,
+            InProgress,
+            // longest path to a final node, if any is reachable
+            Done(Option<usize>),
+        }
+
+        let n = self.final_nodes.size;
+        let mut successors = vec![Vec::new(); n];
+        for matrix in self.token_matrices.values() {
+            for ((to, from), value) in matrix.enumerate_iter() {
+                if *value {
+                    successors[from].push(to);
+                }
+            }
+        }
+
+        // depth-first search from the start node; reaching a node that is in
+        // progress means there's a reachable cycle
+        let mut marks = vec![Mark::Unvisited; n];
+        marks[0] = Mark::InProgress;
+        let mut stack = vec![(0, 0)];
+        while let Some((node, next)) = stack.last().cloned() {
+            if let Some(&successor) = successors[node].get(next) {
+                stack.last_mut().unwrap().1 += 1;
+                match marks[successor] {
+                    Mark::InProgress => return None,
+                    Mark::Unvisited => {
+                        marks[successor] = Mark::InProgress;
+                        stack.push((successor, 0));
+                    }
+                    Mark::Done(_) => {}
+                }
+            } else {
+                stack.pop();
+                let longest = successors[node]
+                    .iter()
+                    .filter_map(|s| match marks[*s] {
+                        Mark::Done(len) => len.map(|len| len + 1),
+                        _ => unreachable!("successors should be done"),
+                    })
+                    .max();
+                let own = self.final_nodes.get(node).then_some(0);
+                marks[node] = Mark::Done(own.max(longest));
+            }
+        }
+
+        match marks[0] {
+            Mark::Done(len) => Some(len.unwrap_or(0)),
+            _ => unreachable!("start node should be done"),
+        }
+    }
+}
+
+/// Wraps every match in `string` in `prefix` and `suffix`, such as the ANSI
+/// escapes `"\x1b[91m"` and `"\x1b[m"` to color the matches in a terminal
+///
+/// `matches` are `(start, len)` pairs like those of [`Regex::find_all`], in
+/// any order. Overlapping matches are wrapped as one, adjacent ones
+/// separately, and empty ones not at all.
+///
+/// returns: the wrapped string, encoded as UTF-8
+pub fn highlight(
+    string: &[UnicodeCodepoint],
+    matches: &[(usize, usize)],
+    prefix: &str,
+    suffix: &str,
+) -> String {
+    let mut spans: Vec<(usize, usize)> = matches
+        .iter()
+        .filter(|(_, len)| *len > 0)
+        .map(|(start, len)| (*start, start + len))
+        .collect();
+    spans.sort_unstable();
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start < last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    let mut out = String::with_capacity(crate::utf8::utf8_len(string));
+    let mut prev = 0;
+    for (start, end) in merged {
+        out.push_str(&crate::utf8::encode_utf8_string(&string[prev..start]));
+        out.push_str(prefix);
+        out.push_str(&crate::utf8::encode_utf8_string(&string[start..end]));
+        out.push_str(suffix);
+        prev = end;
+    }
+    out.push_str(&crate::utf8::encode_utf8_string(&string[prev..]));
+    out
+}
+
+#[cfg(feature = "std")]
+fn parse_result<T>(
+    outcome: Option<Result<T, parsable::ParseErrorStack>>,
+    source: &[u8],
+) -> Result<T, RegexParseError> {
+    match outcome {
+        None => Err(RegexParseError::MissingParseResultError),
+        Some(result) => result.map_err(|stack| {
+            let position = stack.first().map_or(0, |e| e.source_position);
+            let excerpt = excerpt(source, position);
+            RegexParseError::ParseError { stack, excerpt }
+        }),
+    }
+}
+
+/// returns: up to 16 bytes of `source` on each side of `position`, widened
+/// to whole codepoints
+#[cfg(feature = "std")]
+fn excerpt(source: &[u8], position: usize) -> String {
+    let is_continuation =
+        |i: usize| source.get(i).is_some_and(|b| b >> 6 == 0b10);
+    let mut start = position.saturating_sub(16);
+    while start > 0 && is_continuation(start) {
+        start -= 1;
+    }
+    let mut end = position.saturating_add(16).min(source.len());
+    while end < source.len() && is_continuation(end) {
+        end += 1;
+    }
+    String::from_utf8_lossy(&source[start.min(end)..end]).into_owned()
+}
+
+/// returns: `line` without a trailing `\n` or `\r\n`
+fn strip_line_end(line: &[UnicodeCodepoint]) -> &[UnicodeCodepoint] {
+    match line.strip_suffix(&['\n'.into()]) {
+        Some(line) => line.strip_suffix(&['\r'.into()]).unwrap_or(line),
+        None => line,
+    }
+}
+
+/// returns: the `(from, to)` pairs of the transitions in `matrix`
+fn matrix_edges(matrix: &BitMatrix) -> impl Iterator<Item = (u32, u32)> + '_ {
+    (0..matrix.size_i).flat_map(move |to| {
+        matrix
+            .row(to)
+            .zip(0_usize..)
+            .filter(|(value, _)| *value)
+            .map(move |(_, from)| (state_index(from), state_index(to)))
+    })
+}
+
+fn state_index(i: usize) -> u32 {
+    u32::try_from(i).expect("state count should fit in a u32")
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::utf8;
+    use crate::utf8::UnicodeError;
+
+    #[test]
+    fn regex_test() {
+        fn test(r: &str, s: &str) -> bool {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .test(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert!(test("", ""));
+        assert!(!test("", "a"));
+
+        assert!(!test("a", "ab"));
+        assert!(!test("ab", "a"));
+
+        assert!(test("a(a(b|cd)*|ab)*c", "ac"));
+        assert!(test("a(a(b|cd)*|ab)*c", "aac"));
+        assert!(test("a(a(b|cd)*|ab)*c", "aabbbbabc"));
+        assert!(test("a(a(b|cd)*|ab)*c", "aabbabacdcdabc"));
+
+        assert!(!test("a(a(b|cd)*|ab)*c", ""));
+        assert!(!test("a(a(b|cd)*|ab)*c", "a"));
+        assert!(!test("a(a(b|cd)*|ab)*c", "c"));
+    }
+
+    #[test]
+    fn regex_test_stops_early() {
+        let regex = Regex::new(b"abc").unwrap();
+        // every character is in the pattern, but no state is left after the
+        // second `a`, so nothing after it is read
+        let rest = core::iter::once_with(|| panic!("read past a dead end"));
+        assert!(!regex.test_chars("aba".chars().chain(rest)));
+    }
+
+    #[test]
+    fn regex_step() {
+        fn stepped(regex: &Regex, s: &str) -> bool {
+            let mut state = regex.initial_state();
+            let mut next = state.clone();
+            for c in s.chars() {
+                regex.step(&state, c.into(), &mut next);
+                core::mem::swap(&mut state, &mut next);
+            }
+            regex.is_accepting(&state)
+        }
+
+        let patterns = ["", "a", "a(a(b|cd)*|ab)*c", "^ab$", "(^a|b)*", "a$|"];
+        let strings = ["", "a", "b", "ab", "ac", "aabbabacdcdabc", "ba", "x"];
+        for r in patterns {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            for s in strings {
+                let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+                assert_eq!(stepped(&regex, s), regex.test(&string), "{r} {s}");
+            }
+        }
+
+        // the states can be inspected and compared between steps
+        let regex = Regex::new(b"ab").unwrap();
+        let start = regex.initial_state();
+        let mut state = start.clone();
+        regex.step(&start, 'x'.into(), &mut state);
+        assert!(state.is_empty());
+        regex.step(&start, 'a'.into(), &mut state);
+        assert!(!state.is_empty() && state != start);
+        assert!(!regex.is_accepting(&state));
+    }
+
+    #[test]
+    fn regex_empty_alternatives() {
+        fn test(r: &str, s: &str) -> bool {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .test(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        // an empty alternative matches the empty string, wherever it is
+        for r in ["a|", "|a", "a||b", "(a|)b", "|"] {
+            assert!(Regex::new(r.as_bytes()).is_ok(), "{r:?}");
+        }
+        assert!(test("a|", ""));
+        assert!(test("a|", "a"));
+        assert!(test("|a", ""));
+        assert!(test("|a", "a"));
+        assert!(test("a||b", ""));
+        assert!(test("a||b", "b"));
+        assert!(!test("a||b", "ab"));
+        assert!(test("(a|)b", "b"));
+        assert!(test("|", ""));
+        assert!(!test("|", "a"));
+    }
+
+    #[test]
+    fn regex_highlight() {
+        fn highlight(s: &str, matches: &[(usize, usize)]) -> String {
+            let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+            super::highlight(&string, matches, "[", "]")
+        }
+
+        let string = utf8::decode_utf8("aXbåXXc".as_bytes()).unwrap();
+        let matches = Regex::new(b"X").unwrap().find_all(&string);
+        assert_eq!(
+            super::highlight(&string, &matches, "<", ">"),
+            "a<X>bå<X><X>c"
+        );
+
+        assert_eq!(highlight("abc", &[]), "abc");
+        assert_eq!(highlight("", &[]), "");
+        assert_eq!(highlight("abc", &[(0, 3)]), "[abc]");
+        assert_eq!(highlight("aéb", &[(1, 1)]), "a[é]b");
+        // in any order, overlapping and empty
+        assert_eq!(highlight("abcdef", &[(4, 1), (0, 2)]), "[ab]cd[e]f");
+        assert_eq!(highlight("abcdef", &[(1, 3), (2, 3)]), "a[bcde]f");
+        assert_eq!(highlight("abcdef", &[(1, 4), (2, 1)]), "a[bcde]f");
+        assert_eq!(highlight("abc", &[(1, 0), (3, 0)]), "abc");
+    }
+
+    #[test]
+    fn regex_star_group() {
+        fn test(r: &str, s: &str) -> bool {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .test(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        // a starred group repeats as a whole
+        assert!(test("(ab)*", "abab"));
+        assert!(!test("(ab)*", "aba"));
+        assert!(test("(ab|cd)*", "abcdab"));
+        assert!(!test("(ab|cd)*", "abc"));
+        assert!(!test("(ab|cd)*", "ac"));
+
+        // a later star doesn't lead back into an earlier one
+        assert!(test("a*b*", "aabb"));
+        assert!(!test("a*b*", "ba"));
+        assert!(!test("x(ab)*(cd)*", "xcdab"));
+        assert!(test("x(ab)*(cd)*", "xabcd"));
+        assert!(!test("(a*b*)c", "bac"));
+        assert!(test("(a*b)*", "bab"));
+    }
+
+    #[test]
+    fn regex_empty_star() {
+        fn regex(r: &str) -> Regex {
+            Regex::new(r.as_bytes()).unwrap()
+        }
+        fn test(r: &str, s: &str) -> bool {
+            regex(r).test(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        // a starred group that can't consume anything is an epsilon loop,
+        // which collapses away to the group matching the empty string once
+        assert!(regex("()*") == regex(""));
+        assert!(regex("(|)*") == regex(""));
+        assert!(regex("(()*)*") == regex(""));
+        assert!(regex("a()*b") == regex("ab"));
+        assert!(regex("(a*)*") == regex("a*"));
+        for r in ["()*", "(|)*", "(a*)*", "(a|)*"] {
+            assert!(test(r, ""), "{r:?}");
+            assert!(!test(r, "b"), "{r:?}");
+        }
+        assert!(test("(a*)*", "aaa"));
+        assert!(test("(a|)*", "aaa"));
+
+        // capturing doesn't go around the loop forever either; a loop that
+        // comes back to where it started without consuming anything isn't
+        // taken, so the group doesn't take part
+        let string = utf8::decode_utf8(b"aa").unwrap();
+        assert_eq!(
+            regex("()*a").captures(&string),
+            Some(vec![Some((0, 1)), None])
+        );
+        assert_eq!(
+            regex("(a*)*").captures_full(&string),
+            Some(vec![Some((0, 2)), Some((0, 2))])
+        );
+    }
+
+    #[test]
+    fn regex_test_chars() {
+        let regex = Regex::new(b"a(b|cd)*").unwrap();
+        assert!(regex.test_chars("abcdb".chars()));
+        assert!(regex.test_chars(['a', 'b']));
+        assert!(!regex.test_chars("abc".chars()));
+        assert!(!regex.test_chars("".chars()));
+
+        for s in ["", "a", "abcd", "ba", "abcdx"] {
+            assert_eq!(
+                regex.test_chars(s.chars()),
+                regex.test(&utf8::decode_utf8(s.as_bytes()).unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn regex_new_from_codepoints() {
+        // a(🔥|\*)*, built up one group at a time
+        let mut pattern = vec![UnicodeCodepoint::from('a')];
+        for group in ["(🔥", "|\\*", ")*"] {
+            pattern.extend(group.chars().map(UnicodeCodepoint::from));
+        }
+
+        let regex = Regex::new_from_codepoints(&pattern).unwrap();
+        assert!(regex == Regex::new_from_str("a(🔥|\\*)*").unwrap());
+        assert!(regex.test(&utf8::decode_utf8("a🔥*🔥".as_bytes()).unwrap()));
+        assert!(!regex.test(&utf8::decode_utf8(b"a(").unwrap()));
+
+        assert!(
+            Regex::new_from_codepoints(&[UnicodeCodepoint::from('(')]).is_err()
+        );
+    }
+
+    #[test]
+    fn regex_escaped_punctuation() {
+        fn test(r: &str, s: &str) -> bool {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .test(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert!(test("a\\.b", "a.b"));
+        assert!(!test("a\\.b", "axb"));
+        assert!(test("\\@\\/\\{\\~\\!", "@/{~!"));
+        assert!(test("\\(\\*\\)", "(*)"));
+        // there is no wildcard, so `.` is a literal, escaped or not
+        assert!(test("a.b", "a.b"));
+        assert!(!test("a.b", "axb"));
+        assert!(!test("a.b", "a\nb"));
+
+        assert!(Regex::new(b"\\q").is_err());
+        assert!(Regex::new(b"\\1").is_err());
+        assert!(Regex::new(b"a\\").is_err());
+    }
+
+    #[test]
+    fn regex_repeat() {
+        fn test(r: &str, s: &str) -> bool {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .test(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert!(test("a{3}", "aaa"));
+        assert!(!test("a{3}", "aa"));
+        assert!(!test("a{3}", "aaaa"));
+        assert!(test("(ab|c){2}d", "abcd"));
+        assert!(!test("(ab|c){2}d", "abd"));
+        assert!(test("a{0}b", "b"));
+        assert!(test("(a*b){2}", "aabb"));
+        assert!(test("a{03}}", "aaa}"));
+        assert!(test("\\{3}", "{3}"));
+
+        assert!(Regex::new(b"a{").is_err());
+        assert!(Regex::new(b"a{}").is_err());
+        assert!(Regex::new(b"a{x}").is_err());
+    }
+
+    #[test]
+    fn regex_repeat_too_large() {
+        let options = RegexOptions {
+            max_repeat: 5,
+            ..RegexOptions::default()
+        };
+        assert!(Regex::new_with_options(b"a{5}", &options).is_ok());
+        assert!(matches!(
+            Regex::new_with_options(b"a{6}", &options),
+            Err(RegexError::RepeatTooLarge { max: 5 })
+        ));
+        assert!(matches!(
+            Regex::new_with_options(b"(a|b{6})*", &options),
+            Err(RegexError::RepeatTooLarge { max: 5 })
+        ));
+
+        assert!(Regex::new(b"a{1000}").is_ok());
+        assert!(matches!(
+            Regex::new(b"a{1001}"),
+            Err(RegexError::RepeatTooLarge { max: 1000 })
+        ));
+        assert!(matches!(
+            Regex::new(b"a{99999999999999999999}"),
+            Err(RegexError::RepeatTooLarge { max: 1000 })
+        ));
+        // any number of digits is a count, however far it overflows
+        let digits = "9".repeat(100);
+        assert!(matches!(
+            Regex::new(format!("a{{{digits}}}").as_bytes()),
+            Err(RegexError::RepeatTooLarge { max: 1000 })
+        ));
+        assert!(matches!(
+            Regex::new(format!("a{{0{digits}}}").as_bytes()),
+            Err(RegexError::RepeatTooLarge { max: 1000 })
+        ));
+    }
+
+    #[test]
+    fn regex_automaton_too_large() {
+        let options = RegexOptions {
+            max_states: 10,
+            ..RegexOptions::default()
+        };
+        assert!(Regex::new_with_options(b"a{5}|b", &options).is_ok());
+        assert!(matches!(
+            Regex::new_with_options(b"a{20}", &options),
+            Err(RegexError::AutomatonTooLarge { max_states: 10 })
+        ));
+        assert!(matches!(
+            Regex::new_with_options(b"abcdefghijkl", &options),
+            Err(RegexError::AutomatonTooLarge { max_states: 10 })
+        ));
+
+        // each bound is allowed, but together they'd be a billion states
+        assert!(matches!(
+            Regex::new(b"((a{1000}){1000}){1000}"),
+            Err(RegexError::AutomatonTooLarge { max_states: 4096 })
+        ));
+    }
+
+    #[test]
+    fn regex_matrices_too_large() {
+        // 4 states, and 3 matrices of 16 entries each
+        let options = |max_matrix_entries| RegexOptions {
+            max_matrix_entries,
+            ..RegexOptions::default()
+        };
+        assert!(Regex::new_with_options(b"abc", &options(48)).is_ok());
+        assert!(matches!(
+            Regex::new_with_options(b"abc", &options(47)),
+            Err(RegexError::MatricesTooLarge { max_entries: 47 })
+        ));
+        // codepoints with the same transitions share a matrix, like `a` and
+        // `A` here
+        assert!(Regex::new_with_options(b"(?i:abc)", &options(48)).is_ok());
+
+        // few enough states, but a matrix for every one of 4000 literals
+        let literals: String = ('\u{4e00}'..).take(4000).collect();
+        assert!(matches!(
+            Regex::new(literals.as_bytes()),
+            Err(RegexError::MatricesTooLarge {
+                max_entries: 0x1000_0000
+            })
+        ));
+    }
+
+    #[test]
+    fn regex_too_many_branches() {
+        let options = RegexOptions {
+            max_alternation_branches: 5,
+            ..RegexOptions::default()
+        };
+        let new = |r: &str| Regex::new_with_options(r.as_bytes(), &options);
+        for r in ["a|b|c|d|e", "(a|b)(c|d)", "((((a))))", "(a|)|b|"] {
+            assert!(new(r).is_ok(), "{r:?}");
+        }
+        // alternations are counted across the whole pattern, nested or not
+        for r in [
+            "a|b|c|d|e|f",
+            "(a|b)|(c|d)",
+            "(a|b)(c|d)(e|f)",
+            "((a|b)|c)|d",
+        ] {
+            assert!(
+                matches!(new(r), Err(RegexError::TooManyBranches { max: 5 })),
+                "{r:?}"
+            );
+        }
+
+        let wide = vec!["a"; 10_001].join("|");
+        assert!(matches!(
+            Regex::new(wide.as_bytes()),
+            Err(RegexError::TooManyBranches { max: 10_000 })
+        ));
+    }
+
+    #[test]
+    fn regex_new_with_capacity() {
+        let patterns = ["", "a", "a(a(b|cd)*|ab)*c", "(a|b){3}$", "^(x)y*"];
+        for r in patterns {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            for capacity in [0, 1, 5, 1000, 1 << 40, usize::MAX] {
+                let hinted =
+                    Regex::new_with_capacity(r.as_bytes(), capacity).unwrap();
+                assert!(hinted == regex, "{r:?} {capacity}");
+                let string = utf8::decode_utf8(b"aabcdabcxy").unwrap();
+                assert_eq!(hinted.find_all(&string), regex.find_all(&string));
+                assert_eq!(hinted.captures(&string), regex.captures(&string));
+            }
+        }
+    }
+
+    #[test]
+    fn regex_case_insensitive() {
+        fn test(r: &str, s: &str) -> bool {
+            Regex::new_case_insensitive(r.as_bytes())
+                .unwrap()
+                .test(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert!(test("a", "a"));
+        assert!(test("a", "A"));
+        assert!(test("Ab*", "aBbB"));
+        assert!(test("äö", "ÄÖ"));
+        assert!(test("é", "É"));
+        assert!(test("Σίσυφος", "ΣΊΣΥΦΟΣ"));
+        assert!(test("ΣΊΣΥΦΟΣ", "σίσυφος"));
+        assert!(test("привет", "ПРИВЕТ"));
+        assert!(test("ǆ", "ǅ"));
+        assert!(test("1\\.", "1."));
+        assert!(!test("a", "b"));
+        assert!(test("k", "\u{212a}"));
+        assert!(test("ω", "\u{2126}"));
+        assert!(test("θ", "ϴ"));
+
+        // a literal matches every codepoint with the same folding
+        for c in (0..0x11_0000).filter_map(char::from_u32) {
+            let folded = char::from(utf8::fold_codepoint(c.into()));
+            if folded != c {
+                assert!(test(&folded.to_string(), &c.to_string()), "{c:?}");
+                assert!(test(&c.to_string(), &folded.to_string()), "{c:?}");
+            }
+        }
+
+        let alphabet: Vec<_> = Regex::new_case_insensitive(b"a")
+            .unwrap()
+            .alphabet()
+            .collect();
+        assert_eq!(alphabet, ['A'.into(), 'a'.into()]);
+
+        // `ß` uppercases to `SS`, which isn't a single codepoint, but the
+        // capital `ẞ` still folds to it
+        let alphabet: Vec<_> = Regex::new_case_insensitive("ß".as_bytes())
+            .unwrap()
+            .alphabet()
+            .collect();
+        assert_eq!(alphabet, ['ß'.into(), 'ẞ'.into()]);
+
+        let alphabet: Vec<_> = Regex::new(b"ba*").unwrap().alphabet().collect();
+        assert_eq!(alphabet, ['a'.into(), 'b'.into()]);
+    }
+
+    #[test]
+    fn regex_case_insensitive_group() {
+        fn test(r: &str, s: &str) -> bool {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .test(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert!(test("(?i:ab)c", "ABc"));
+        assert!(test("(?i:ab)c", "aBc"));
+        assert!(!test("(?i:ab)c", "ABC"));
+        assert!(test("(?i:a(?-i:b)c)", "AbC"));
+        assert!(!test("(?i:a(?-i:b)c)", "ABC"));
+        assert!(test("(?i:a|b)*", "AbBa"));
+
+        // the flag ends with the group, even when it's repeated
+        assert!(test("(?i:a){2}b", "Aab"));
+        assert!(!test("(?i:a){2}b", "AaB"));
+
+        let regex = Regex::new_case_insensitive(b"a(?-i:b)").unwrap();
+        let string = |s: &str| utf8::decode_utf8(s.as_bytes()).unwrap();
+        assert!(regex.test(&string("Ab")));
+        assert!(!regex.test(&string("AB")));
+
+        // flag groups don't capture
+        let regex = Regex::new(b"(?i:a)(b)").unwrap();
+        assert_eq!(regex.group_count(), 1);
+        assert_eq!(
+            regex.captures(&string("Ab")),
+            Some(vec![Some((0, 2)), Some((1, 1))])
+        );
+    }
+
+    #[test]
+    fn regex_anchors() {
+        fn regex(r: &str) -> Regex {
+            Regex::new(r.as_bytes()).unwrap()
+        }
+        fn string(s: &str) -> Vec<UnicodeCodepoint> {
+            utf8::decode_utf8(s.as_bytes()).unwrap()
+        }
+
+        assert!(regex("^abc").test(&string("abc")));
+        assert!(regex("abc$").test(&string("abc")));
+        assert!(regex("^$").test(&string("")));
+        assert!(!regex("^$").test(&string("a")));
+        assert!(!regex("a^b").test(&string("ab")));
+        assert!(!regex("a$b").test(&string("ab")));
+        assert!(regex("(^a|b)*").test(&string("ab")));
+        assert!(!regex("(^a|b)*").test(&string("ba")));
+        assert!(regex("\\^\\$").test(&string("^$")));
+
+        assert_eq!(regex("^ab").find(&string("abab")), Some((0, 2)));
+        assert_eq!(regex("^ab").find(&string("xab")), None);
+        assert_eq!(regex("ab$").find(&string("abab")), Some((2, 2)));
+        assert_eq!(regex("$").find(&string("abc")), Some((3, 0)));
+        assert_eq!(regex("^").find(&string("abc")), Some((0, 0)));
+        assert_eq!(regex("a|^b").find(&string("bab")), Some((0, 1)));
+        assert_eq!(regex("a|^b").find(&string("cab")), Some((1, 1)));
+        assert_eq!(regex("(a|b)*$").find(&string("xab")), Some((1, 2)));
+
+        assert_eq!(regex("a$").find_all(&string("aba")), [(2, 1)]);
+        assert_eq!(regex("^a").find_all(&string("aa")), [(0, 1)]);
+        assert_eq!(regex("$").find_all(&string("ab")), [(2, 0)]);
+        assert_eq!(regex("^").find_all(&string("ab")), [(0, 0)]);
+
+        assert_eq!(regex("^a").shortest_match(&string("ab")), Some(1));
+        assert_eq!(regex("a$").shortest_match(&string("aa")), None);
+        assert_eq!(regex("a*$").shortest_match(&string("aa")), Some(2));
+        assert_eq!(regex("$").shortest_match(&string("a")), None);
+        assert_eq!(regex("$").shortest_match(&string("")), Some(0));
+
+        let anchors: Vec<_> = regex("^a|b$")
+            .export()
+            .anchor_edges
+            .into_iter()
+            .map(|(_, _, anchor)| anchor)
+            .collect();
+        assert_eq!(anchors, [Anchor::Start, Anchor::End]);
+
+        assert!(regex("^").matches_empty());
+        assert!(regex("$").matches_empty());
+        assert!(!regex("^a").matches_empty());
+    }
+
+    #[test]
+    fn regex_rfind_at() {
+        let patterns = ["a", "ab|b", "a*b", "ab$", "^a", "$", "", "(a|b)(a|b)"];
+        let strings = ["", "a", "ab", "abab", "bba", "xabxab"];
+        for r in patterns {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            for s in strings {
+                let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+                // the match ending at each index, read to the end
+                let mut all = Vec::new();
+                regex.each_match(&string, string.len(), |start, len| {
+                    all.push((start, len));
+                });
+                for end in 0..=string.len() + 2 {
+                    let expected = all
+                        .iter()
+                        .rev()
+                        .find(|(start, len)| start + len <= end)
+                        .map(|&(start, len)| Match { start, len });
+                    assert_eq!(
+                        regex.rfind_at(&string, end),
+                        expected,
+                        "{r:?} {s:?} {end}"
+                    );
+                }
+            }
+        }
+
+        let regex = Regex::new(b"ab").unwrap();
+        let string = utf8::decode_utf8(b"abxab").unwrap();
+        let rfind_at = |end| regex.rfind_at(&string, end).map(|m| m.range());
+        assert_eq!(rfind_at(5), Some(3..5));
+        assert_eq!(rfind_at(4), Some(0..2));
+        assert_eq!(rfind_at(1), None);
+        assert_eq!(rfind_at(100), Some(3..5));
+    }
+
+    #[test]
+    fn regex_live_nodes() {
+        // after reading `a`, the `$b` branch can't be finished
+        let regex = Regex::new(b"a$b|ac").unwrap();
+        assert!(regex.live_nodes.enumerate_iter().any(|(_, live)| !live));
+        let mut active = Vec::new();
+        let string = utf8::decode_utf8(b"ac").unwrap();
+        regex.find_traced(&string, |_, states| {
+            active.push(states.iter().filter(|s| s.is_some()).count());
+        });
+        assert_eq!(active, [1, 1]);
+
+        // dropping those states doesn't change any result
+        let patterns = ["a$b|ac", "a^b|a*", "(a$|b)*c", "ab$|a", "a(b|$c)d"];
+        let strings = ["", "a", "ab", "ac", "abc", "bbc", "abd", "ad", "a$b"];
+        for r in patterns {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            let mut all_live = Regex::new(r.as_bytes()).unwrap();
+            all_live.live_nodes =
+                BitVector::from_bools(&vec![true; regex.live_nodes.size]);
+            for s in strings {
+                let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+                assert_eq!(regex.test(&string), all_live.test(&string));
+                assert_eq!(regex.find(&string), all_live.find(&string));
+                assert_eq!(
+                    regex.find_all(&string),
+                    all_live.find_all(&string),
+                    "{r:?} {s:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn regex_is_match_at() {
+        fn is_match_at(r: &str, s: &str, start: usize) -> bool {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .is_match_at(&utf8::decode_utf8(s.as_bytes()).unwrap(), start)
+        }
+
+        // anchored at `start`, unlike `find`, which searches on
+        assert!(is_match_at("b", "ab", 1));
+        assert!(!is_match_at("b", "ab", 0));
+        let regex = Regex::new(b"b").unwrap();
+        assert_eq!(
+            regex.find(&utf8::decode_utf8(b"ab").unwrap()),
+            Some((1, 1))
+        );
+
+        assert!(is_match_at("ab|b*c", "xbbc", 1));
+        assert!(!is_match_at("ab|b*c", "xbbc", 0));
+        assert!(is_match_at("a*", "xyz", 1));
+        assert!(is_match_at("", "x", 1));
+        assert!(!is_match_at("x", "x", 1));
+
+        // `^` only at the very start, `$` only at the very end
+        assert!(is_match_at("^a", "aa", 0));
+        assert!(!is_match_at("^a", "aa", 1));
+        assert!(is_match_at("a$", "aa", 1));
+        assert!(!is_match_at("a$", "aab", 1));
+        assert!(is_match_at("$", "ab", 2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn regex_is_match_at_out_of_range() {
+        let regex = Regex::new(b"a").unwrap();
+        regex.is_match_at(&utf8::decode_utf8(b"a").unwrap(), 2);
+    }
+
+    #[test]
+    fn regex_find_fully_anchored() {
+        let regex = Regex::new(b"^abc$").unwrap();
+        assert!(regex.is_anchored_start() && regex.is_anchored_end());
+        let find =
+            |s: &str| regex.find(&utf8::decode_utf8(s.as_bytes()).unwrap());
+        assert_eq!(find("abc"), Some((0, 3)));
+        assert_eq!(find("xabc"), None);
+        assert_eq!(find("abcx"), None);
+        assert_eq!(find(""), None);
+
+        // the same as the general search
+        for r in ["^abc$", "^a*$", "^$", "^a$|^b$", "^(a|b)(a|b)*$"] {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            assert!(regex.is_anchored_start() && regex.is_anchored_end());
+            for s in ["", "a", "b", "ab", "abc", "ba", "c"] {
+                let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+                assert_eq!(
+                    regex.find(&string),
+                    regex.find_with_reason(&string).ok(),
+                    "{r:?} {s:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn regex_find_end_anchor() {
+        fn find(r: &str, s: &str) -> Option<(usize, usize)> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .find(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        // a `$` is only followed after the last codepoint, so a final state
+        // behind it doesn't count before then
+        assert_eq!(find("ab$", "abc"), None);
+        assert_eq!(find("ab$", "xab"), Some((1, 2)));
+        assert_eq!(find("ab$", "abxab"), Some((3, 2)));
+        assert_eq!(find("ab$|c", "abc"), Some((2, 1)));
+        assert_eq!(find("a$|ab", "ab"), Some((0, 2)));
+        assert_eq!(find("a$", ""), None);
+        assert_eq!(find("a*$", ""), Some((0, 0)));
+    }
+
+    #[test]
+    fn regex_is_anchored() {
+        fn anchored(r: &str) -> (bool, bool) {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            (regex.is_anchored_start(), regex.is_anchored_end())
+        }
+
+        assert_eq!(anchored("^abc"), (true, false));
+        assert_eq!(anchored("abc$"), (false, true));
+        assert_eq!(anchored("^abc$"), (true, true));
+        assert_eq!(anchored("^(a|b)c*"), (true, false));
+        assert_eq!(anchored("a$|b$"), (false, true));
+        assert_eq!(anchored("^a|b"), (false, false));
+        assert_eq!(anchored("(^a)*"), (false, false));
+        assert_eq!(anchored("abc"), (false, false));
+        assert_eq!(anchored(""), (false, false));
+
+        // a start-anchored regex only tries index 0: after `a` fails to be
+        // followed by `b`, nothing else is attempted
+        let mut string = utf8::decode_utf8(b"ac").unwrap();
+        string.extend(utf8::decode_utf8(&b"ab".repeat(1000)).unwrap());
+        let regex = Regex::new(b"^ab").unwrap();
+        assert_eq!(regex.find_with_budget(&string, 1), Ok(None));
+        let regex = Regex::new(b"ab").unwrap();
+        assert!(regex.find_with_budget(&string, 1).is_err());
+    }
+
+    #[test]
+    fn regex_parse_error_context() {
+        let Err(e) = Regex::parse_ast(b"abc)def") else {
+            panic!()
+        };
+        assert_eq!(e.context(), Some((3, String::from("abc)def"))));
+
+        // only the part around the error is kept
+        let source = "0123456789abcdefghij)klmnopqrstuvwxyz";
+        let Err(e) = Regex::parse_ast(source.as_bytes()) else {
+            panic!()
+        };
+        let excerpt = String::from("456789abcdefghij)klmnopqrstuvwxy");
+        assert_eq!(e.context(), Some((20, excerpt)));
+
+        // without cutting a codepoint in half
+        let source = "€€€€€€)";
+        let Err(e) = Regex::parse_ast(source.as_bytes()) else {
+            panic!()
+        };
+        assert_eq!(e.context(), Some((18, String::from(source))));
+    }
+
+    #[test]
+    fn regex_new_with_warnings() {
+        fn warnings(r: &str) -> Vec<Warning> {
+            let (regex, warnings) =
+                Regex::new_with_warnings(r.as_bytes()).unwrap();
+            assert!(regex == Regex::new(r.as_bytes()).unwrap());
+            warnings
+        }
+
+        assert_eq!(warnings("(a*)*"), [Warning::NullableStar]);
+        assert_eq!(warnings("(a|)*b"), [Warning::NullableStar]);
+        assert_eq!(warnings("(^)*"), [Warning::NullableStar]);
+        assert_eq!(warnings("a|b|a"), [Warning::DuplicateAlternative]);
+        assert_eq!(warnings("x(a*|a*)"), [Warning::DuplicateAlternative]);
+        assert_eq!(
+            warnings("(a|a)*|(b*)*"),
+            [Warning::DuplicateAlternative, Warning::NullableStar]
+        );
+        assert!(warnings("a*").is_empty());
+        assert!(warnings("(ab)*|ba").is_empty());
+        // escaped and unescaped `@` are written differently
+        assert!(warnings("\\@|@").is_empty());
+        assert!(Regex::new_with_warnings(b"a)").is_err());
+    }
+
+    #[test]
+    fn regex_new_verbose() {
+        assert!(
+            Regex::new_verbose(b"a(b|c)*").unwrap()
+                == Regex::new(b"a(b|c)*").unwrap()
+        );
+
+        // parsing stops at the first error, so only the first `)` is reported
+        let Err(details) = Regex::new_verbose(b"a)b)") else {
+            panic!("expected an error");
+        };
+        assert!(!details.is_empty());
+        assert!(details.iter().all(|d| d.position == Some(1)));
+        let Err(RegexError::ParseError(e)) = Regex::new(b"a)b)") else {
+            panic!("expected a parse error");
+        };
+        assert_eq!(e.details(), details);
+        assert!(e.to_string().contains(&details[0].message));
+
+        let Err(details) = Regex::new_verbose(b"a{1001}") else {
+            panic!("expected an error");
+        };
+        assert_eq!(
+            details,
+            [ParseErrorDetail {
+                position: None,
+                message: RegexError::RepeatTooLarge { max: 1000 }.to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn regex_parse_prefix() {
+        fn parse_prefix(r: &str) -> (Regex, usize) {
+            Regex::parse_prefix(r.as_bytes()).unwrap()
+        }
+        fn test(regex: &Regex, s: &str) -> bool {
+            regex.test(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        let (regex, consumed) = parse_prefix("a*b)rest");
+        assert_eq!(consumed, 3);
+        assert!(test(&regex, "aab"));
+        assert!(!test(&regex, "aab)rest"));
+
+        let (regex, consumed) = parse_prefix("(a|b)c|d)x)");
+        assert_eq!(consumed, 8);
+        assert!(test(&regex, "bc"));
+        assert!(test(&regex, "d"));
+
+        let (regex, consumed) = parse_prefix("🔥|a");
+        assert_eq!(consumed, "🔥|a".len());
+        assert!(test(&regex, "🔥"));
+
+        let (regex, consumed) = parse_prefix(")abc");
+        assert_eq!(consumed, 0);
+        assert!(test(&regex, ""));
+
+        assert!(Regex::parse_prefix(b"a(b").is_err());
+    }
+
+    #[test]
+    fn regex_multi_byte_characters() {
+        for c in ['é', '中', '🔥'] {
+            let regex = Regex::new_from_str(&c.to_string()).unwrap();
+            assert_eq!(regex.alphabet().collect::<Vec<_>>(), [c.into()]);
+            assert_eq!(regex.export().edges.len(), 1);
+            assert!(regex.test(&[c.into()]));
+        }
+        assert!(
+            Regex::new_from_str("🔥*a")
+                .unwrap()
+                .test_chars("🔥🔥a".chars())
+        );
+
+        // a stray continuation byte, and a truncated sequence
+        assert!(Regex::new(b"\xc3\xa9\xa9").is_err());
+        assert!(Regex::new(b"\xf0\x9f\x94").is_err());
+    }
+
+    #[test]
+    fn regex_surrogate_codepoint() {
+        // UTF-8 encoding of the surrogate U+D800
+        let result = Regex::new(b"a\xed\xa0\x80");
+        assert!(matches!(
+            result,
+            Err(RegexError::Utf8DecodeError(Utf8DecodeError::UnicodeError(
+                UnicodeError::SurrogateCodepoint(0xd800)
+            )))
+        ));
+
+        // a well-formed 4-byte sequence for U+110000, past the last codepoint
+        let result = Regex::new(b"a\xf4\x90\x80\x80");
+        assert!(matches!(
+            result,
+            Err(RegexError::Utf8DecodeError(Utf8DecodeError::UnicodeError(
+                UnicodeError::OutsideOfRange(0x11_0000)
+            )))
+        ));
+    }
+
+    #[test]
+    fn regex_error_source() {
+        use core::error::Error;
+
+        // the wrapped error is the source, so error chains can be walked,
+        // and the outer message doesn't repeat it
+        let Err(e) = Regex::new(b"a)") else { panic!() };
+        assert_eq!(e.to_string(), "invalid pattern");
+        let source = e.source().unwrap();
+        assert!(source.downcast_ref::<RegexParseError>().is_some());
+
+        let Err(e) = Regex::new(b"a\xed\xa0\x80") else {
+            panic!()
+        };
+        assert_eq!(e.to_string(), "invalid utf8 in pattern");
+        let source = e.source().unwrap();
+        assert!(source.downcast_ref::<Utf8DecodeError>().is_some());
+        assert_eq!(source.to_string(), "invalid unicode codepoint");
+        let source = source.source().unwrap();
+        assert_eq!(
+            source.downcast_ref::<UnicodeError>(),
+            Some(&UnicodeError::SurrogateCodepoint(0xd800))
+        );
+
+        // the other errors don't wrap anything
+        let Err(e) = Regex::new(b"a{1001}") else {
+            panic!()
+        };
+        assert!(e.source().is_none());
+        let Err(e) = Regex::new(b"(?P<a>x)(?P<a>y)") else {
+            panic!()
+        };
+        assert!(e.source().is_none());
+    }
+
+    #[test]
+    fn regex_control_characters() {
+        // control characters, NUL included, can't be written in a pattern
+        for pattern in [&b"\0"[..], b"a\0", b"(\0)", b"\x01", b"\n", b"\x7f"] {
+            assert!(matches!(
+                Regex::new(pattern),
+                Err(RegexError::ParseError(_))
+            ));
+        }
+
+        // but they can be skipped over in the input
+        let nul = UnicodeCodepoint::from('\0');
+        let regex = Regex::new(b"ab").unwrap();
+        assert_eq!(
+            regex.find(&[nul, 'a'.into(), 'b'.into(), nul]),
+            Some((1, 2))
+        );
+        assert!(!regex.test(&['a'.into(), nul, 'b'.into()]));
+    }
+
+    #[test]
+    fn regex_matches_empty() {
+        fn matches_empty(r: &str) -> bool {
+            Regex::new(r.as_bytes()).unwrap().matches_empty()
+        }
+
+        assert!(matches_empty(""));
+        assert!(matches_empty("a*"));
+        assert!(matches_empty("(|a)"));
+        assert!(matches_empty("(a*|b)c{0}"));
+        assert!(!matches_empty("a"));
+        assert!(!matches_empty("aa*"));
+        assert!(!matches_empty("a*b"));
+    }
+
+    #[test]
+    fn regex_is_empty_pattern() {
+        fn regex(r: &str) -> Regex {
+            Regex::new(r.as_bytes()).unwrap()
+        }
+
+        for r in ["", "()", "(|)*", "a{0}"] {
+            assert!(regex(r).is_empty_pattern(), "{r:?}");
+        }
+        for r in ["a", "a*", "^", "$", "a|"] {
+            assert!(!regex(r).is_empty_pattern(), "{r:?}");
+        }
+
+        // `find_all` finds the same empty matches as splitting and replacing,
+        // whether or not it has to read the string to know they're there
+        let string = utf8::decode_utf8(b"abc").unwrap();
+        let every_index = [(0, 0), (1, 0), (2, 0), (3, 0)];
+        for r in ["", "x*"] {
+            let regex = regex(r);
+            assert_eq!(regex.find_all(&string), every_index, "{r:?}");
+            assert_eq!(regex.split(&string).len(), every_index.len() + 1);
+            let dash = [UnicodeCodepoint::from('-')];
+            let replaced = regex.replacen(&string, &dash, usize::MAX);
+            assert_eq!(utf8::encode_utf8_string(&replaced), "-a-b-c-");
+        }
+        assert_eq!(regex("").find_all(&[]), [(0, 0)]);
+    }
+
+    #[test]
+    fn regex_final_states() {
+        fn final_states(r: &str) -> Vec<usize> {
+            Regex::new(r.as_bytes()).unwrap().final_states()
+        }
+
+        assert!(final_states("").contains(&0));
+        assert!(final_states("a*").contains(&0));
+        assert!(final_states("(|a)b*").contains(&0));
+        assert!(!final_states("a").contains(&0));
+        assert!(!final_states("a").is_empty());
+        // the final node the pattern is compiled towards is only reached
+        // through epsilon edges, so it's removed along with them
+        assert_eq!(final_states(""), [0]);
+        assert_eq!(final_states("ab"), [2]);
+
+        let regex = Regex::new(b"ab|c*").unwrap();
+        let export = regex.export();
+        for i in 0..export.state_count {
+            assert_eq!(
+                regex.final_states().contains(&i),
+                export.final_states[i / 64] & (1 << (i % 64)) != 0
+            );
+        }
+    }
+
+    #[test]
+    fn regex_alphabet() {
+        let regex = Regex::new("z🔥(yä|b)*a\\|".as_bytes()).unwrap();
+        let alphabet: Vec<_> = regex.alphabet().collect();
+        assert!(alphabet.is_sorted());
+        assert_eq!(alphabet.len(), 7);
+
+        // the same pattern gives the same order
+        let again = Regex::new("z🔥(yä|b)*a\\|".as_bytes()).unwrap();
+        assert_eq!(alphabet, again.alphabet().collect::<Vec<_>>());
+        assert_eq!(regex.export(), again.export());
+    }
+
+    #[test]
+    fn regex_export() {
+        fn run(export: &AutomatonExport, string: &[UnicodeCodepoint]) -> bool {
+            let mut active = vec![false; export.state_count];
+            active[0] = true;
+            for token in string {
+                let mut next = vec![false; export.state_count];
+                for (from, to, t) in &export.edges {
+                    if t == token && active[*from as usize] {
+                        next[*to as usize] = true;
+                    }
+                }
+                active = next;
+            }
+            active.iter().zip(0_usize..).any(|(active, i)| {
+                *active && export.final_states[i / 64] & (1 << (i % 64)) != 0
+            })
+        }
+
+        let patterns = ["", "a", "ab|b", "a(a(b|cd)*|ab)*c", "(🔥|x)*y"];
+        let strings = ["", "a", "b", "ab", "aac", "aabbabacdcdabc", "🔥x🔥y"];
+        for r in patterns {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            let export = regex.export();
+            assert_eq!(export.state_count, regex.final_nodes.size);
+            assert!(export.anchor_edges.is_empty());
+            for s in strings {
+                let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+                assert_eq!(run(&export, &string), regex.test(&string));
+            }
+        }
+    }
+
+    #[test]
+    fn regex_eq() {
+        fn regex(r: &str) -> Regex {
+            Regex::new(r.as_bytes()).unwrap()
+        }
+
+        assert!(regex("a(b|cd)*") == regex("a(b|cd)*"));
+        assert!(Regex::new_from_str("🔥*").unwrap() == regex("🔥*"));
+        assert!(regex("\\@\\.") == regex("@\\."));
+        assert!(regex("(a|b)c") == regex("(a|b)c"));
+
+        assert!(regex("a") != regex("b"));
+        assert!(regex("a") != regex("a*"));
+        assert!(regex("ab") != regex("ba"));
+        assert!(regex("") != regex("a"));
+
+        // redundant structure compiles away without a separate pass
+        assert!(regex("(a)") == regex("a"));
+        assert!(regex("((a))") == regex("a"));
+        assert!(regex("(?:a)") == regex("a"));
+        assert!(regex("(a*)*b") == regex("a*b"));
+        assert!(regex("(a|b)|c") == regex("a|b|c"));
+        assert!(regex("a{1}") == regex("a"));
+        // and a quantifier can't be quantified again
+        assert!(Regex::new(b"a**").is_err());
+    }
+
+    #[test]
+    fn regex_shortest_match() {
+        fn shortest_match(r: &str, s: &str) -> Option<usize> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .shortest_match(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert_eq!(shortest_match("a*", "aaa"), Some(0));
+        assert_eq!(shortest_match("aa*", "aaa"), Some(1));
+        assert_eq!(shortest_match("", ""), Some(0));
+
+        assert_eq!(shortest_match("ab|abc", "abcd"), Some(2));
+        assert_eq!(shortest_match("a(b|cd)*c", "abbbcdc"), Some(5));
+
+        assert_eq!(shortest_match("ab", "a"), None);
+        assert_eq!(shortest_match("b", "ab"), None);
+    }
+
+    #[test]
+    fn regex_leftmost_match() {
+        fn leftmost(r: &str, s: &str) -> Option<(usize, usize)> {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+            let found = regex.leftmost_match(&string);
+            assert_eq!(found, regex.find_match(&string));
+            found.map(|m| (m.start(), m.len()))
+        }
+
+        // the leftmost start wins, even when a later match is shorter
+        assert_eq!(leftmost("abc|b", "abc"), Some((0, 3)));
+        assert_eq!(leftmost("ab*c|b", "abbbc"), Some((0, 5)));
+        // from that start, the shortest match wins, whatever the order of
+        // the alternatives
+        assert_eq!(leftmost("ab|a", "ab"), Some((0, 1)));
+        assert_eq!(leftmost("a|ab", "ab"), Some((0, 1)));
+        assert_eq!(leftmost("ab*", "abbb"), Some((0, 1)));
+        assert_eq!(leftmost("a*", "aa"), Some((0, 0)));
+        // a match found first can still lose to one that started earlier
+        assert_eq!(leftmost("abcd|c", "abcd"), Some((0, 4)));
+        assert_eq!(leftmost("abcd|c", "abcx"), Some((2, 1)));
+        assert_eq!(leftmost("b*c", "abbc"), Some((1, 3)));
+        assert_eq!(leftmost("a*$", "baa"), Some((1, 2)));
+        assert_eq!(leftmost("ab", "ba"), None);
+    }
+
+    #[test]
+    fn regex_find() {
+        fn find(r: &str, s: &str) -> Option<(usize, usize)> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .find(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert_eq!(find("", ""), Some((0, 0)));
+        assert_eq!(find("", "a"), Some((0, 0)));
+
+        // patterns that match the empty string behave like the empty pattern,
+        // since the shortest match is preferred
+        assert_eq!(find("a*", ""), Some((0, 0)));
+        assert_eq!(find("a*", "b"), Some((0, 0)));
+        assert_eq!(find("a*", "aa"), Some((0, 0)));
+        assert_eq!(find("(a|b*)c*", "ba"), Some((0, 0)));
+        assert_eq!(find("a*$", "b"), Some((1, 0)));
+        assert_eq!(find("a*$", "aa"), Some((0, 2)));
+
+        assert_eq!(find("a", "ab"), Some((0, 1)));
+        assert_eq!(find("ab", "a"), None);
+
+        assert_eq!(find("a(a(b|cd)*|ab)*c", "ac"), Some((0, 2)));
         assert_eq!(find("a(a(b|cd)*|ab)*c", "aac"), Some((0, 3)));
         assert_eq!(find("a(a(b|cd)*|ab)*c", "aabbbbabc"), Some((0, 9)));
         assert_eq!(find("a(a(b|cd)*|ab)*c", "aabbabacdcdabc"), Some((0, 8)));
@@ -271,4 +3137,904 @@ mod tests {
 
         assert_eq!(find("ab", "acab"), Some((2, 2)));
     }
+
+    #[test]
+    fn regex_unknown_tokens() {
+        fn find(r: &str, s: &str) -> Option<(usize, usize)> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .find(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+        fn test(r: &str, s: &str) -> bool {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .test(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        // a codepoint outside the alphabet ends every attempt so far, but
+        // `find` starts new ones after it
+        assert_eq!(find("ab", "xabx"), Some((1, 2)));
+        assert_eq!(find("ab", "axb"), None);
+        assert_eq!(find("ab", "axab"), Some((2, 2)));
+        assert_eq!(find("abc", "abxabc"), Some((3, 3)));
+        assert_eq!(find("a*b", "aaxab"), Some((3, 2)));
+        assert_eq!(find("x*", "ab"), Some((0, 0)));
+
+        // while `test` has to match all of the string
+        assert!(!test("ab", "xab"));
+        assert!(!test("ab", "abx"));
+        assert!(!test("a*", "axa"));
+        assert!(test("a*", "aa"));
+    }
+
+    #[test]
+    fn regex_find_range() {
+        fn find_range(r: &str, s: &str) -> Option<String> {
+            let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+            let found =
+                Regex::new(r.as_bytes()).unwrap().find_range(&string)?;
+            Some(utf8::encode_utf8_string(&string[found.range()]))
+        }
+
+        assert_eq!(find_range("bc*d", "abccde").as_deref(), Some("bccd"));
+        assert_eq!(find_range("🔥a", "ä🔥🔥a").as_deref(), Some("🔥a"));
+        assert_eq!(find_range("a*", "b").as_deref(), Some(""));
+        assert_eq!(find_range("x", "abc"), None);
+
+        let regex = Regex::new(b"b").unwrap();
+        let string = utf8::decode_utf8(b"abc").unwrap();
+        assert_eq!(regex.find_range(&string).map(|m| m.range()), Some(1..2));
+    }
+
+    #[test]
+    fn regex_find_traced() {
+        let regex = Regex::new(b"ab*c").unwrap();
+        let string = utf8::decode_utf8(b"xabbc").unwrap();
+        let mut steps = Vec::new();
+        let found = regex.find_traced(&string, |index, states| {
+            steps.push((index, states.to_vec()));
+        });
+        assert_eq!(found, Some(Match { start: 1, len: 4 }));
+        assert_eq!(steps.len(), string.len());
+        assert_eq!(
+            steps.iter().map(|(index, _)| *index).collect::<Vec<_>>(),
+            [0, 1, 2, 3, 4]
+        );
+        // `x` isn't in the alphabet, so nothing is active after it
+        assert!(steps[0].1.iter().all(Option::is_none));
+        // after `a`, `abb` and `abbc`, exactly one attempt is active, and it
+        // started at `a`
+        for (_, states) in &steps[1..] {
+            let active: Vec<_> = states.iter().flatten().collect();
+            assert_eq!(active, [&1]);
+        }
+        let final_states = regex.final_states();
+        let last = &steps[4].1;
+        assert!(final_states.iter().any(|i| last[*i] == Some(1)));
+
+        // reading stops at the first match
+        let mut count = 0;
+        regex.find_traced(&utf8::decode_utf8(b"acxx").unwrap(), |_, _| {
+            count += 1;
+        });
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn regex_find_chars() {
+        let regex = Regex::new(b"bc*d").unwrap();
+        let find_chars = |chars: &mut core::str::Chars| {
+            regex.find_chars(chars).map(|m| (m.start(), m.len()))
+        };
+        assert_eq!(find_chars(&mut "abcd".chars()), Some((1, 3)));
+        assert_eq!(find_chars(&mut "🔥🔥bd".chars()), Some((2, 2)));
+        assert_eq!(find_chars(&mut "abc".chars()), None);
+
+        // reading stops at the end of the match
+        let mut chars = "xbdyz".chars();
+        assert_eq!(find_chars(&mut chars), Some((1, 2)));
+        assert_eq!(chars.as_str(), "yz");
+    }
+
+    #[test]
+    fn regex_find_utf8() {
+        fn find_utf8(
+            r: &str,
+            s: &[u8],
+        ) -> Result<Option<(usize, usize)>, Utf8DecodeError> {
+            let found = Regex::new(r.as_bytes()).unwrap().find_utf8(s)?;
+            Ok(found.map(|m| (m.start(), m.len())))
+        }
+
+        assert_eq!(find_utf8("bc*d", b"abccd"), Ok(Some((1, 4))));
+        assert_eq!(find_utf8("bc*d", "🔥bd🔥".as_bytes()), Ok(Some((4, 2))));
+        assert_eq!(find_utf8("éé*", "aéé".as_bytes()), Ok(Some((1, 2))));
+        assert_eq!(find_utf8("🔥$", "a🔥".as_bytes()), Ok(Some((1, 4))));
+        assert_eq!(find_utf8("x*", b"abc"), Ok(Some((0, 0))));
+        assert_eq!(find_utf8("a$", b"aba"), Ok(Some((2, 1))));
+        assert_eq!(find_utf8("$", b"ab"), Ok(Some((2, 0))));
+        assert_eq!(find_utf8("x", b"abc"), Ok(None));
+        assert_eq!(
+            find_utf8("x", b"a\xa0x"),
+            Err(Utf8DecodeError::InvalidByte2(0xa0, b'x'))
+        );
+        // reading stops at the match, before the invalid byte
+        assert_eq!(find_utf8("a", b"a\xa0"), Ok(Some((0, 1))));
+
+        // every kind of invalid input is the same error as from decoding it
+        // up front, never a panic or a match at a cut-off end
+        let invalid: [&[u8]; 8] = [
+            b"a\xa0",
+            b"a\xc3",
+            b"a\xc3\x28",
+            b"a\xc0\x80",
+            b"a\xed\xa0\x80",
+            b"a\xf0\x9f\x94",
+            b"a\xf4\x90\x80\x80",
+            b"a\xf8\x80\x80\x80",
+        ];
+        for utf8 in invalid {
+            let expected = Err(utf8::decode_utf8(utf8).unwrap_err());
+            for r in ["x", "a$", "a*$", "$"] {
+                assert_eq!(find_utf8(r, utf8), expected, "{r:?} {utf8:x?}");
+            }
+        }
+
+        // a large buffer, with the match near the end
+        let mut buffer = "é".repeat(1 << 20).into_bytes();
+        buffer.extend_from_slice("x🔥y".as_bytes());
+        let expected = Some((1 << 21, 6));
+        assert_eq!(find_utf8("x🔥y", &buffer), Ok(expected));
+    }
+
+    #[test]
+    fn regex_find_match() {
+        fn find_match(r: &str, s: &str) -> Option<Match> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .find_match(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        let m = find_match("ab*c", "xxabbbcy").unwrap();
+        assert_eq!(m.start(), 2);
+        assert_eq!(m.len(), 5);
+        assert_eq!(m.end(), 7);
+        assert_eq!(m.range(), 2..7);
+        assert!(!m.is_empty());
+
+        let m = find_match("a*", "bbb").unwrap();
+        assert_eq!(m.end(), 0);
+        assert_eq!(m.range(), 0..0);
+        assert!(m.is_empty());
+
+        assert_eq!(find_match("ab", "ba"), None);
+    }
+
+    #[test]
+    fn regex_captures() {
+        fn captures(r: &str, s: &str) -> Option<Vec<Option<(usize, usize)>>> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .captures(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert_eq!(
+            captures("(a)(b)", "xab"),
+            Some(vec![Some((1, 2)), Some((1, 1)), Some((2, 1))])
+        );
+        assert_eq!(
+            captures("((a)b)", "ab"),
+            Some(vec![Some((0, 2)), Some((0, 2)), Some((0, 1))])
+        );
+        assert_eq!(
+            captures("(a|(b))c", "ac"),
+            Some(vec![Some((0, 2)), Some((0, 1)), None])
+        );
+        assert_eq!(
+            captures("(a*)b", "aab"),
+            Some(vec![Some((0, 3)), Some((0, 2))])
+        );
+        // the last repetition
+        assert_eq!(
+            captures("(ab)*c", "ababc"),
+            Some(vec![Some((0, 5)), Some((2, 2))])
+        );
+        assert_eq!(
+            captures("(a){2}", "aa"),
+            Some(vec![Some((0, 2)), Some((1, 1))])
+        );
+        assert_eq!(
+            captures("^(a)|(b)$", "ab"),
+            Some(vec![Some((0, 1)), Some((0, 1)), None])
+        );
+        assert_eq!(captures("a", "ba"), Some(vec![Some((1, 1))]));
+        assert_eq!(captures("(a)", "b"), None);
+
+        assert_eq!(Regex::new(b"a").unwrap().group_count(), 0);
+        assert_eq!(Regex::new(b"(a(b))|(c){3}").unwrap().group_count(), 3);
+    }
+
+    #[test]
+    fn regex_captures_deep_empty_groups() {
+        // a long chain of epsilon edges, which used to be followed with one
+        // stack frame per edge; run on a small stack, so that a recursion
+        // that deep would overflow it
+        let pattern = "(|)".repeat(1000) + "a";
+        let regex = Regex::new(pattern.as_bytes()).unwrap();
+        let string = utf8::decode_utf8(b"ba").unwrap();
+        let groups = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(move || regex.captures(&string))
+            .unwrap()
+            .join()
+            .unwrap()
+            .unwrap();
+        assert_eq!(groups.len(), 1001);
+        assert_eq!(groups[0], Some((1, 1)));
+        assert!(groups[1..].iter().all(|g| *g == Some((1, 0))));
+    }
+
+    #[test]
+    fn regex_captures_named() {
+        fn captures_named(r: &str, s: &str) -> Option<Vec<(String, String)>> {
+            let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+            let groups =
+                Regex::new(r.as_bytes()).unwrap().captures_named(&string)?;
+            Some(
+                groups
+                    .into_iter()
+                    .map(|(name, (start, len))| {
+                        let text = &string[start..start + len];
+                        (name, utf8::encode_utf8_string(text))
+                    })
+                    .collect(),
+            )
+        }
+        fn pairs(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+            Some(
+                pairs
+                    .iter()
+                    .map(|(a, b)| (String::from(*a), String::from(*b)))
+                    .collect(),
+            )
+        }
+
+        let digits = "(?:0|1|2|3|4|5|6|7|8|9)";
+        let date = format!("(?P<year>{digits}{{4}})-(?P<month>{digits}{{2}})");
+        assert_eq!(
+            captures_named(&date, "on 2025-10-16"),
+            pairs(&[("month", "10"), ("year", "2025")])
+        );
+        assert_eq!(captures_named(&date, "2025"), None);
+
+        // named groups are numbered along with the others
+        let regex = Regex::new(b"(a)(?P<b_2>b)(c)").unwrap();
+        assert_eq!(regex.group_count(), 3);
+        let string = utf8::decode_utf8(b"abc").unwrap();
+        assert_eq!(regex.captures(&string).unwrap()[2], Some((1, 1)));
+
+        // groups that don't take part are left out
+        assert_eq!(
+            captures_named("(?P<x>x)|(?P<y>y)", "y"),
+            pairs(&[("y", "y")])
+        );
+        assert_eq!(captures_named("(?P<A>a){3}", "aaa"), pairs(&[("A", "a")]));
+
+        assert!(matches!(
+            Regex::new(b"(?P<a>x)(?P<a>y)"),
+            Err(RegexError::DuplicateGroupName { name }) if name == "a"
+        ));
+        for r in ["(?P<>a)", "(?P<a-b>a)", "(?P<a a)", "(?P<é>a)"] {
+            assert!(Regex::new(r.as_bytes()).is_err(), "{r:?}");
+        }
+    }
+
+    #[test]
+    fn regex_captures_find_iter() {
+        let ab = "(?:a|b)";
+        let digit = "(?:0|1)";
+        let r = format!("({ab}{ab})=({digit}{digit});");
+        let regex = Regex::new(r.as_bytes()).unwrap();
+        let s = "ab=01; ba=10;xx;bb=11;";
+        let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+        let text = |group: Option<(usize, usize)>| {
+            let (start, len) = group.unwrap();
+            utf8::encode_utf8_string(&string[start..start + len])
+        };
+
+        let records: Vec<_> = regex
+            .captures_find_iter(&string)
+            .map(|(m, groups)| (m.start(), text(groups[1]), text(groups[2])))
+            .collect();
+        assert_eq!(
+            records,
+            [
+                (0, String::from("ab"), String::from("01")),
+                (7, String::from("ba"), String::from("10")),
+                (16, String::from("bb"), String::from("11")),
+            ]
+        );
+
+        // the same matches as `find_all`, since they don't overlap
+        let matches: Vec<_> = regex
+            .captures_find_iter(&string)
+            .map(|(m, groups)| {
+                assert_eq!(groups[0], Some((m.start(), m.len())));
+                (m.start(), m.len())
+            })
+            .collect();
+        assert_eq!(matches, regex.find_all(&string));
+
+        // the buffers kept between matches don't carry groups over
+        let regex = Regex::new(b"(a)|(b)").unwrap();
+        let string = utf8::decode_utf8(b"abba").unwrap();
+        let groups: Vec<_> = regex
+            .captures_find_iter(&string)
+            .map(|(_, groups)| (groups[1], groups[2]))
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                (Some((0, 1)), None),
+                (None, Some((1, 1))),
+                (None, Some((2, 1))),
+                (Some((3, 1)), None),
+            ]
+        );
+
+        // empty matches are stepped over, and come after non-empty ones
+        let regex = Regex::new(b"(a)*").unwrap();
+        let string = utf8::decode_utf8(b"ba").unwrap();
+        let matches: Vec<_> = regex
+            .captures_find_iter(&string)
+            .map(|(m, groups)| (m.start(), m.len(), groups[1]))
+            .collect();
+        assert_eq!(matches, [(0, 0, None), (1, 1, Some((1, 1))), (2, 0, None)]);
+        assert_eq!(regex.captures_find_iter(&[]).count(), 1);
+    }
+
+    #[test]
+    fn regex_captures_full() {
+        fn captures_full(
+            r: &str,
+            s: &str,
+        ) -> Option<Vec<Option<(usize, usize)>>> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .captures_full(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert_eq!(
+            captures_full("(a*)-(b*)", "aa-bbb"),
+            Some(vec![Some((0, 6)), Some((0, 2)), Some((3, 3))])
+        );
+        assert_eq!(
+            captures_full("(a*)-(b*)", "-"),
+            Some(vec![Some((0, 1)), Some((0, 0)), Some((1, 0))])
+        );
+        assert_eq!(
+            captures_full("x(a|(b))*", "xab"),
+            Some(vec![Some((0, 3)), Some((2, 1)), Some((2, 1))])
+        );
+        assert_eq!(captures_full("a*", ""), Some(vec![Some((0, 0))]));
+
+        // only a prefix or a substring matches
+        assert_eq!(captures_full("(a*)-(b*)", "aa-bbc"), None);
+        assert_eq!(captures_full("(a*)-(b*)", "caa-b"), None);
+        let regex = Regex::new(b"(a)b").unwrap();
+        let string = utf8::decode_utf8(b"abc").unwrap();
+        assert!(regex.captures(&string).is_some());
+        assert_eq!(regex.captures_full(&string), None);
+    }
+
+    #[test]
+    fn regex_non_capturing_group() {
+        fn regex(r: &str) -> Regex {
+            Regex::new(r.as_bytes()).unwrap()
+        }
+        fn string(s: &str) -> Vec<UnicodeCodepoint> {
+            utf8::decode_utf8(s.as_bytes()).unwrap()
+        }
+
+        let regex_abc = regex("(?:ab)c");
+        assert!(regex_abc.test(&string("abc")));
+        assert!(!regex_abc.test(&string("c")));
+        assert_eq!(regex_abc.group_count(), 0);
+        assert_eq!(
+            regex_abc.captures(&string("abc")),
+            Some(vec![Some((0, 3))])
+        );
+
+        assert!(regex("(?:a|b)*").test(&string("abba")));
+        assert_eq!(regex("(?:a(b))(?:c)(d)").group_count(), 2);
+        assert_eq!(
+            regex("(?:a)(b)").captures(&string("ab")),
+            Some(vec![Some((0, 2)), Some((1, 1))])
+        );
+        assert_eq!(regex("(?:(a)){2}(b)").group_count(), 2);
+
+        // atomic groups are parsed, but don't capture or commit to anything
+        let regex_atomic = regex("(?>ab)c");
+        assert!(regex_atomic.test(&string("abc")));
+        assert!(regex_atomic == regex_abc);
+        assert_eq!(regex_atomic.group_count(), 0);
+        assert!(regex("(?>a*)a").test(&string("aa")));
+        assert_eq!(regex("(?>(a)|b)(c)").group_count(), 2);
+
+        // `?` on its own is still a literal
+        assert!(regex("(?a)").test(&string("?a")));
+        assert!(regex("(?)").test(&string("?")));
+    }
+
+    #[test]
+    fn regex_new_never_panics() {
+        // every pattern of up to 3 bytes from the syntax characters and a
+        // few bytes that aren't valid UTF-8 on their own, see also the
+        // `regex_new` fuzz target
+        let bytes = b"()|*^$\\{},09a?P<>:i-.\xc3\xa9\xff";
+        let string = utf8::decode_utf8(b"ab,a").unwrap();
+        for len in 0..=3 {
+            for k in 0..bytes.len().pow(len) {
+                let pattern: Vec<_> = (0..len)
+                    .map(|i| bytes[k / bytes.len().pow(i) % bytes.len()])
+                    .collect();
+                if let Ok(regex) = Regex::new(&pattern) {
+                    regex.find(&string);
+                    regex.captures(&string);
+                }
+                // the options constructors, and nested groups that can all
+                // be empty
+                _ = Regex::new_case_insensitive(&pattern);
+                _ = Regex::new_with_capacity(&pattern, usize::MAX);
+                _ = Regex::new_with_warnings(&pattern);
+                let nested = [b"((", &pattern[..], b"|)|)"].concat();
+                if let Ok(regex) = Regex::new(&nested) {
+                    regex.captures_find_iter(&string).count();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn regex_new_never_panics_regressions() {
+        let string = utf8::decode_utf8(b"ab,a").unwrap();
+
+        // a capacity hint that overflowed when allocating the graph
+        let regex = Regex::new_with_capacity(b"(a)|b", usize::MAX).unwrap();
+        assert_eq!(regex.captures(&string), Some(vec![Some((0, 1)); 2]));
+
+        // a chain of empty groups that overflowed the stack when capturing,
+        // see also `regex_captures_deep_empty_groups`
+        let pattern = "(|)".repeat(1000) + "a";
+        let regex = Regex::new(pattern.as_bytes()).unwrap();
+        let groups = regex.captures(&string).unwrap();
+        assert_eq!(groups[0], Some((0, 1)));
+        assert!(groups[1..].iter().all(|g| *g == Some((0, 0))));
+    }
+
+    #[test]
+    fn regex_splitn() {
+        fn splitn(r: &str, s: &str, n: usize) -> Vec<String> {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+            regex
+                .splitn(&string, n)
+                .iter()
+                .map(|part| utf8::encode_utf8_string(part))
+                .collect()
+        }
+
+        assert_eq!(splitn(",", "a,b,c,d", 2), ["a", "b,c,d"]);
+        assert_eq!(splitn(",", "a,b,c,d", 3), ["a", "b", "c,d"]);
+        assert_eq!(splitn(",", "a,b,c,d", 4), ["a", "b", "c", "d"]);
+        assert_eq!(splitn(",", "a,b,c,d", 10), ["a", "b", "c", "d"]);
+        assert_eq!(splitn(",", "a,b,c,d", 1), ["a,b,c,d"]);
+        assert!(splitn(",", "a,b,c,d", 0).is_empty());
+        assert_eq!(splitn(",", "a,", 2), ["a", ""]);
+        assert_eq!(splitn(",", "", 2), [""]);
+        assert_eq!(splitn("x*", "abc", 2), ["", "abc"]);
+        assert_eq!(splitn("x*", "abc", 3), ["", "a", "bc"]);
+    }
+
+    #[test]
+    fn regex_split() {
+        fn split(r: &str, s: &str) -> Vec<String> {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+            let parts: Vec<_> = regex
+                .split_iter(&string)
+                .map(utf8::encode_utf8_string)
+                .collect();
+            let eager: Vec<_> = regex
+                .split(&string)
+                .iter()
+                .map(|part| utf8::encode_utf8_string(part))
+                .collect();
+            assert_eq!(parts, eager);
+            parts
+        }
+
+        assert_eq!(split(",", "a,b,,c"), ["a", "b", "", "c"]);
+        assert_eq!(split(",", ",a,"), ["", "a", ""]);
+        assert_eq!(split(",", ""), [""]);
+        assert_eq!(split(",", "abc"), ["abc"]);
+        // matches are as short as possible
+        assert_eq!(split(", *", "a, b,c"), ["a", " b", "c"]);
+        assert_eq!(split("ab|b", "xabbx"), ["x", "", "x"]);
+        // empty matches split between every codepoint, like `str::split("")`
+        assert_eq!(split("x*", "ab"), ["", "a", "b", ""]);
+        assert_eq!(split("^a", "aaa"), ["", "aa"]);
+        assert_eq!(split("a$", "aaa"), ["aa", ""]);
+        assert_eq!(split("🔥", "a🔥b"), ["a", "b"]);
+    }
+
+    #[test]
+    fn regex_find_all_empty_matches() {
+        fn find_all(r: &str, s: &str) -> Vec<(usize, usize)> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .find_all(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        // the matches don't overlap, and each is as short as possible
+        assert_eq!(find_all("a", "aba"), [(0, 1), (2, 1)]);
+        assert_eq!(find_all("ab|b", "abb"), [(0, 2), (2, 1)]);
+        assert_eq!(find_all("(ab)*", "abab"), [(0, 2), (2, 2), (4, 0)]);
+        // but not empty where a non-empty match starts too, and there's an
+        // empty match at every other index where one starts
+        assert_eq!(find_all("a*", "aba"), [(0, 1), (1, 0), (2, 1), (3, 0)]);
+        assert_eq!(find_all("a|b*", "ab"), [(0, 1), (1, 1), (2, 0)]);
+        assert_eq!(find_all("a*", "bb"), [(0, 0), (1, 0), (2, 0)]);
+        assert_eq!(find_all("a*", ""), [(0, 0)]);
+        // with anchors, only where they hold
+        assert_eq!(find_all("a*$", "ab"), [(2, 0)]);
+        assert_eq!(find_all("b*$", "ab"), [(1, 1), (2, 0)]);
+        assert_eq!(find_all("^a*", "aab"), [(0, 1)]);
+    }
+
+    #[test]
+    fn regex_test_suffix() {
+        fn test_suffix(r: &str, s: &str) -> bool {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .test_suffix(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert!(test_suffix("bc", "abc"));
+        assert!(!test_suffix("bc", "abx"));
+        assert!(!test_suffix("bc", "bcx"));
+        assert!(test_suffix("bc", "bc"));
+        assert!(!test_suffix("bc", ""));
+        assert!(test_suffix("a*", "xyz"));
+        assert!(test_suffix("ab*", "xabbb"));
+        assert!(test_suffix("b|cd", "xcd"));
+        assert!(test_suffix("b$", "ab"));
+        assert!(test_suffix("^ab", "ab"));
+        assert!(!test_suffix("^b", "ab"));
+        assert!(test_suffix("🔥", "a🔥"));
+    }
+
+    #[test]
+    fn regex_test_line() {
+        fn test_line(r: &str, s: &str) -> bool {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .test_line(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert!(test_line("ab*", "abb"));
+        assert!(test_line("ab*", "abb\n"));
+        assert!(test_line("ab*", "abb\r\n"));
+        assert!(test_line("ab*$", "abb\r\n"));
+        assert!(!test_line("ab*", "abb\n\n"));
+        assert!(!test_line("ab*", "abbc\n"));
+        assert!(test_line("", "\n"));
+        // a lone `\r` isn't a line end
+        assert!(!test_line("ab*", "abb\r"));
+    }
+
+    #[test]
+    fn regex_grep_lines() {
+        fn grep_lines(r: &str, s: &str) -> Vec<(usize, Range<usize>)> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .grep_lines(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        let input = "foo\nbar baz\r\n\nbaaz\nqux\n";
+        let lines = grep_lines("baa*z", input);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], (1, 4..7));
+        assert_eq!(lines[1], (3, 0..4));
+
+        // `$` holds at the end of every line, before any `\r`
+        let lines = grep_lines("z$", "baz\r\nzz\nza");
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], (0, 2..3));
+        assert_eq!(lines[1], (1, 1..2));
+
+        // like `str::lines`, a final line end doesn't start another line
+        assert_eq!(grep_lines("", "a\nb\n").len(), 2);
+        assert_eq!(grep_lines("", "a\n\n").len(), 2);
+        assert!(grep_lines("", "").is_empty());
+        assert!(grep_lines("x", "a\nb").is_empty());
+    }
+
+    #[test]
+    fn regex_test_by() {
+        fn test_by(
+            r: &str,
+            s: &str,
+            eq: impl Fn(UnicodeCodepoint, UnicodeCodepoint) -> bool,
+        ) -> bool {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .test_by(&utf8::decode_utf8(s.as_bytes()).unwrap(), eq)
+        }
+        fn eq_ignore_case(a: UnicodeCodepoint, b: UnicodeCodepoint) -> bool {
+            let a = char::from(a).to_lowercase();
+            a.eq(char::from(b).to_lowercase())
+        }
+
+        assert!(test_by("abc", "ABC", eq_ignore_case));
+        assert!(test_by("abc", "aBc", eq_ignore_case));
+        assert!(!test_by("abc", "ABC", |a, b| a == b));
+        assert!(!test_by("abc", "ABD", eq_ignore_case));
+        assert!(test_by("(a|b)*c$", "ABBAC", eq_ignore_case));
+        assert!(test_by("", "", eq_ignore_case));
+        // a codepoint can be read as more than one token
+        assert!(test_by("ab|bc", "xx", |_, _| true));
+        assert!(!test_by("ab|bc", "xxx", |_, _| true));
+    }
+
+    #[test]
+    fn regex_test_partial() {
+        fn test_partial(r: &str, s: &str) -> PartialMatch {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .test_partial_chars(s.chars())
+        }
+
+        assert_eq!(test_partial("abc", "abc"), PartialMatch::Complete);
+        assert_eq!(test_partial("abc", "ab"), PartialMatch::NeedsMore);
+        assert_eq!(test_partial("abc", ""), PartialMatch::NeedsMore);
+        assert_eq!(test_partial("abc", "abx"), PartialMatch::Rejected);
+        assert_eq!(test_partial("abc", "abcd"), PartialMatch::Rejected);
+        assert_eq!(test_partial("a*", ""), PartialMatch::Complete);
+        assert_eq!(test_partial("a*b", "aaa"), PartialMatch::NeedsMore);
+        assert_eq!(test_partial("ab|cd", "c"), PartialMatch::NeedsMore);
+        assert_eq!(test_partial("ab$", "a"), PartialMatch::NeedsMore);
+        assert_eq!(test_partial("ab$", "ab"), PartialMatch::Complete);
+        assert_eq!(test_partial("^$", ""), PartialMatch::Complete);
+        assert_eq!(test_partial("^$", "a"), PartialMatch::Rejected);
+        // reading stops at the first codepoint that can't lead to a match
+        let mut read = 0;
+        let chars = "axbbb".chars().inspect(|_| read += 1);
+        let regex = Regex::new(b"ab*").unwrap();
+        assert_eq!(regex.test_partial_chars(chars), PartialMatch::Rejected);
+        assert_eq!(read, 2);
+    }
+
+    #[test]
+    fn regex_earliest_matches() {
+        fn earliest_matches(r: &str, s: &str) -> Vec<Option<usize>> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .earliest_matches(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert_eq!(
+            earliest_matches("ab*c", "abbcac"),
+            [Some(4), None, None, None, Some(6), None]
+        );
+        assert_eq!(
+            earliest_matches("a|ab|bc", "abc"),
+            [Some(1), Some(3), None]
+        );
+        assert_eq!(earliest_matches("x*", "ab"), [Some(0), Some(1)]);
+        assert_eq!(earliest_matches("a$", "aa"), [None, Some(2)]);
+        assert_eq!(earliest_matches("^a", "aa"), [Some(1), None]);
+        assert!(earliest_matches("a", "").is_empty());
+
+        // every entry is the shortest match of the rest of the string
+        for r in ["a(b|cd)*c", "(a|bc)*(c|db)", "a*b|ba", "(a|b)*$"] {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            let string = utf8::decode_utf8(b"abcbcdcadbaab").unwrap();
+            let ends = regex.earliest_matches(&string);
+            for (start, end) in ends.iter().enumerate() {
+                let expected =
+                    regex.shortest_match(&string[start..]).map(|l| start + l);
+                assert_eq!(*end, expected, "{r:?} from {start}");
+            }
+            let first = ends
+                .iter()
+                .enumerate()
+                .find_map(|(start, end)| end.map(|end| (start, end - start)));
+            assert_eq!(regex.find(&string), first, "{r:?}");
+        }
+    }
+
+    #[test]
+    fn regex_match_mask() {
+        fn match_mask(r: &str, s: &str) -> Vec<bool> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .match_mask(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        // overlapping
+        assert_eq!(
+            match_mask("aba", "ababa"),
+            [true, false, true, false, false]
+        );
+        assert_eq!(match_mask("ab|b", "abb"), [true, true, true]);
+        // non-overlapping
+        assert_eq!(
+            match_mask("ab", "abxab"),
+            [true, false, false, true, false]
+        );
+        assert_eq!(match_mask("a*", "bab"), [true, true, true]);
+        assert_eq!(match_mask("x", ""), []);
+
+        assert_eq!(match_mask("^a", "aa"), [true, false]);
+        assert_eq!(match_mask("a$", "aa"), [false, true]);
+        assert_eq!(match_mask("a*$", "aba"), [false, false, true]);
+        assert_eq!(match_mask("(^|b)a", "aba"), [true, true, false]);
+
+        // a match starts at `i` if some substring starting at `i` matches
+        for r in ["", "a", "ab|b", "a(a(b|cd)*|ab)*c", "(a|bc)*(c|db)"] {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            let string = utf8::decode_utf8(b"aabcdcabcbcdbaacdb").unwrap();
+            let expected: Vec<_> = (0..string.len())
+                .map(|i| (i..=string.len()).any(|j| regex.test(&string[i..j])))
+                .collect();
+            assert_eq!(regex.match_mask(&string), expected, "{r:?}");
+        }
+    }
+
+    #[test]
+    fn regex_find_all_str() {
+        fn find_all_str(r: &str, s: &str) -> Vec<String> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .find_all_str(s)
+                .into_iter()
+                .map(|m| s[m.range()].to_string())
+                .collect()
+        }
+
+        assert_eq!(find_all_str("b", "abcb"), ["b", "b"]);
+        assert_eq!(find_all_str("🔥a", "ä🔥a中🔥a"), ["🔥a", "🔥a"]);
+        assert_eq!(find_all_str("中*文", "中中文x文"), ["中中文", "文"]);
+        assert_eq!(find_all_str("é$", "éaé"), ["é"]);
+        assert!(find_all_str("x", "äö").is_empty());
+
+        let ranges = Regex::new("ö".as_bytes()).unwrap().find_all_str("äöü");
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].range(), 2..4);
+        let ranges = Regex::new(b"$").unwrap().find_all_str("äö");
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].range(), 4..4);
+    }
+
+    #[test]
+    fn regex_find_matches_find_all() {
+        let cases = [
+            ("", ""),
+            ("", "a"),
+            ("a", "ab"),
+            ("ab", "a"),
+            ("a(a(b|cd)*|ab)*c", "ac"),
+            ("a(a(b|cd)*|ab)*c", "aac"),
+            ("a(a(b|cd)*|ab)*c", "aabbbbabc"),
+            ("a(a(b|cd)*|ab)*c", "aabbabacdcdabc"),
+            ("a(a(b|cd)*|ab)*c", ""),
+            ("a(a(b|cd)*|ab)*c", "a"),
+            ("a(a(b|cd)*|ab)*c", "c"),
+            ("(a|bc)*(c|db)", "abcbcdcadb"),
+            ("(a|bc)*db", "abcbcdcadb"),
+            ("aba|b", "aba"),
+            ("abb*|b", "abbba"),
+            ("A*aB*|D", "AAAa||||b\\"),
+            ("🔥*a\\|*|\\\\", "🔥🔥🔥a||||b\\"),
+            ("🔥*a\\|*b|\\\\", "🔥🔥🔥a||||b\\"),
+            ("🔥*a\\|*|\\\\", "🔥🔥🔥||||b\\"),
+            ("ab", "acab"),
+            ("^ab", "abab"),
+            ("ab$", "abab"),
+            ("$", "abc"),
+            ("a|^b", "bab"),
+            ("(a|b)*$", "xab"),
+        ];
+        for (r, s) in cases {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+            // the leftmost, then shortest, match reported by `find_all`
+            let expected = regex.find_all(&string).into_iter().min();
+            assert_eq!(regex.find(&string), expected, "{r:?} on {s:?}");
+        }
+    }
+
+    #[cfg(feature = "grapheme")]
+    #[test]
+    fn regex_find_grapheme_bounds() {
+        fn find(r: &str, s: &str) -> Option<(usize, usize)> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .find_grapheme_bounds(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        // "e" followed by a combining acute accent is a single cluster
+        assert_eq!(find("e", "xe\u{301}y"), Some((1, 2)));
+        assert_eq!(find("\u{301}", "xe\u{301}y"), Some((1, 2)));
+        assert_eq!(find("e\u{301}y", "xe\u{301}y"), Some((1, 3)));
+        assert_eq!(find("x", "xe\u{301}y"), Some((0, 1)));
+        assert_eq!(find("", "e\u{301}"), Some((0, 0)));
+        assert_eq!(find("z", "e\u{301}"), None);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn regex_find_all_parallel() {
+        let string: Vec<_> = "abcabbcacbcaabcx🔥"
+            .chars()
+            .cycle()
+            .take(100_000)
+            .map(UnicodeCodepoint::from)
+            .collect();
+
+        for r in [
+            "",
+            "a",
+            "ab|bca|c(a|b)",
+            "(a|b)(c|a)(b|c)(c|a)",
+            "a*b",
+            "a|",
+            "ab|c*",
+            "x🔥",
+        ] {
+            let regex = Regex::new(r.as_bytes()).unwrap();
+            assert_eq!(
+                regex.find_all_parallel(&string),
+                regex.find_all(&string),
+                "{r:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn regex_find_with_budget() {
+        let regex = Regex::new(b"ab").unwrap();
+        let string = vec![UnicodeCodepoint::from('a'); 1000];
+        assert_eq!(
+            regex.find_with_budget(&string, 10),
+            Err(BudgetExceeded { max_steps: 10 })
+        );
+        assert_eq!(regex.find_with_budget(&string, 1000), Ok(None));
+
+        let string = utf8::decode_utf8(b"xxabab").unwrap();
+        // codepoints that can't be part of a match don't take a step
+        let found = Match { start: 2, len: 2 };
+        assert_eq!(regex.find_with_budget(&string, 2), Ok(Some(found)));
+        assert!(regex.find_with_budget(&string, 1).is_err());
+
+        let regex = Regex::new(b"a*").unwrap();
+        let found = Match { start: 0, len: 0 };
+        assert_eq!(regex.find_with_budget(&string, 0), Ok(Some(found)));
+    }
+
+    #[test]
+    fn regex_find_with_reason() {
+        fn find(r: &str, s: &str) -> Result<(usize, usize), FindFailure> {
+            Regex::new(r.as_bytes())
+                .unwrap()
+                .find_with_reason(&utf8::decode_utf8(s.as_bytes()).unwrap())
+        }
+
+        assert_eq!(find("ab", "xab"), Ok((1, 2)));
+        assert_eq!(find("ab", "xa"), Err(FindFailure::EndOfInput));
+        assert_eq!(find("ab", ""), Err(FindFailure::EndOfInput));
+        assert_eq!(find("ab", "xax"), Err(FindFailure::Rejected));
+        assert_eq!(find("abc", "abd"), Err(FindFailure::Rejected));
+    }
 }
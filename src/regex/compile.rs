@@ -0,0 +1,171 @@
+use crate::regex::parse::{AltExpr, Atom, ConcatExpr, GroupKind, Quantifier};
+use crate::regex::pattern::{self, Pattern};
+use crate::regex::{Regex, RegexError, RegexOptions};
+use crate::utf8::{UnicodeCodepoint, fold_codepoint, unfold_extra};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// returns: the regex matching `alt`
+pub fn compile(
+    alt: &AltExpr,
+    options: &RegexOptions,
+) -> Result<Regex, RegexError> {
+    // checked before lowering, since the graph grows with every branch; the
+    // alternations that case insensitive literals lower to aren't counted
+    if branches_in(alt) > options.max_alternation_branches {
+        return Err(RegexError::TooManyBranches {
+            max: options.max_alternation_branches,
+        });
+    }
+
+    let mut lowering = Lowering {
+        options,
+        case_insensitive: options.case_insensitive,
+        group_count: 0,
+        group_names: BTreeMap::new(),
+    };
+    let pattern = lowering.lower_alts(alt)?;
+    pattern::compile_with_names(&pattern, lowering.group_names, options)
+}
+
+/// Turns a parsed pattern into a [`Pattern`], which is what gets compiled
+struct Lowering<'a> {
+    options: &'a RegexOptions,
+    /// whether literals also match their other cases at this point of the
+    /// pattern, which `(?i:...)` and `(?-i:...)` change
+    case_insensitive: bool,
+    /// the number of groups lowered so far
+    group_count: usize,
+    /// the number of every named group lowered so far
+    group_names: BTreeMap<String, usize>,
+}
+
+impl Lowering<'_> {
+    fn lower_alts(
+        &mut self,
+        alt: &AltExpr,
+    ) -> Result<Pattern<UnicodeCodepoint>, RegexError> {
+        let alts = alt
+            .alts
+            .nodes
+            .iter()
+            .map(|a| self.lower_alt(a))
+            .collect::<Result<_, _>>()?;
+        Ok(Pattern::Alt(alts))
+    }
+
+    fn lower_alt(
+        &mut self,
+        alt: &ConcatExpr,
+    ) -> Result<Pattern<UnicodeCodepoint>, RegexError> {
+        let mut parts = Vec::with_capacity(alt.parts.nodes.len());
+        for p in &alt.parts.nodes {
+            let atom = self.lower_atom(&p.atom)?;
+            parts.push(match &p.quantifier {
+                None => atom,
+                Some(Quantifier::Star(_)) => Pattern::Star(Box::new(atom)),
+                Some(Quantifier::Count { count, .. }) => {
+                    // a count that doesn't fit in a `usize` is over any limit
+                    let count =
+                        count.value().ok_or(RegexError::RepeatTooLarge {
+                            max: self.options.max_repeat,
+                        })?;
+                    Pattern::Repeat(Box::new(atom), count)
+                }
+            });
+        }
+        Ok(Pattern::Concat(parts))
+    }
+
+    fn lower_atom(
+        &mut self,
+        atom: &Atom,
+    ) -> Result<Pattern<UnicodeCodepoint>, RegexError> {
+        match atom {
+            Atom::Character(c) => {
+                let token =
+                    c.to_codepoint().map_err(RegexError::Utf8DecodeError)?;
+                if self.case_insensitive {
+                    let variants = case_variants(token);
+                    Ok(Pattern::Alt(
+                        variants.into_iter().map(Pattern::Token).collect(),
+                    ))
+                } else {
+                    Ok(Pattern::Token(token))
+                }
+            }
+            Atom::StartAnchor(_) => Ok(Pattern::Start),
+            Atom::EndAnchor(_) => Ok(Pattern::End),
+            Atom::Capture { kind, alt, .. }
+                if !GroupKind::is_capturing(kind.as_ref()) =>
+            {
+                // a flag only applies inside its group
+                let case_insensitive = self.case_insensitive;
+                match kind {
+                    Some(GroupKind::CaseInsensitive) => {
+                        self.case_insensitive = true;
+                    }
+                    Some(GroupKind::CaseSensitive) => {
+                        self.case_insensitive = false;
+                    }
+                    _ => {}
+                }
+                let result = self.lower_alts(alt);
+                self.case_insensitive = case_insensitive;
+                result
+            }
+            Atom::Capture { kind, alt, .. } => {
+                self.group_count += 1;
+                if let Some(GroupKind::Named { name, .. }) = kind {
+                    let name = String::from_utf8(name.span.clone())
+                        .expect("group names should be ascii");
+                    if self.group_names.contains_key(&name) {
+                        return Err(RegexError::DuplicateGroupName { name });
+                    }
+                    self.group_names.insert(name, self.group_count);
+                }
+                Ok(Pattern::Group(Box::new(self.lower_alts(alt)?)))
+            }
+        }
+    }
+}
+
+/// returns: the number of alternatives of every alternation in `alt`
+/// together, including `alt` itself, where a part without `|` is one
+fn branches_in(alt: &AltExpr) -> usize {
+    let inner: usize = alt
+        .alts
+        .nodes
+        .iter()
+        .flat_map(|a| &a.parts.nodes)
+        .map(|p| match &p.atom {
+            Atom::Capture { alt, .. } => branches_in(alt),
+            _ => 0,
+        })
+        .sum();
+    alt.alts.nodes.len() + inner
+}
+
+/// returns: `token`, every codepoint with the same case folding, and their
+/// simple uppercase mappings; mappings to more than one codepoint (like `ß`
+/// to `SS`) are left out
+fn case_variants(token: UnicodeCodepoint) -> Vec<UnicodeCodepoint> {
+    fn single(mut mapping: impl Iterator<Item = char>) -> Option<char> {
+        let c = mapping.next()?;
+        mapping.next().is_none().then_some(c)
+    }
+
+    let folded = fold_codepoint(token);
+    let mut variants = Vec::from([token, folded]);
+    variants.extend(unfold_extra(folded));
+    for c in [token, folded] {
+        variants.extend(
+            single(char::from(c).to_uppercase()).map(UnicodeCodepoint::from),
+        );
+    }
+    variants.sort_unstable();
+    variants.dedup();
+    variants
+}
@@ -1,21 +1,76 @@
 use crate::math::{BitMatrix, BitVector};
-use crate::utf8::UnicodeCodepoint;
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::regex::{Anchor, RegexError, RegexOptions};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 static GRAPH_ID: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Debug)]
-pub struct Graph {
-    nodes: Vec<Node>,
+pub struct Graph<T> {
+    nodes: Vec<Node<T>>,
     id: usize,
 }
 
-#[derive(Clone, Debug, Default)]
-struct Node {
+#[derive(Clone, Debug)]
+struct Node<T> {
     is_final: bool,
-    edges: Vec<(usize, UnicodeCodepoint)>,
-    epsilon_edges: Vec<usize>,
+    edges: Vec<(usize, T)>,
+    anchor_edges: Vec<(usize, Anchor)>,
+    /// targets, and the capture slot to record the position in, if any
+    epsilon_edges: Vec<(usize, Option<usize>)>,
+}
+
+// derived `Default` would require `T: Default`
+impl<T> Default for Node<T> {
+    fn default() -> Self {
+        Node {
+            is_final: false,
+            edges: Vec::new(),
+            anchor_edges: Vec::new(),
+            epsilon_edges: Vec::new(),
+        }
+    }
+}
+
+/// The matrices of a compiled graph, see [`Graph::compile`]
+pub struct CompiledGraph<T> {
+    /// tokens with the same transitions share a matrix
+    pub token_matrices: BTreeMap<T, Arc<BitMatrix>>,
+    /// `None` if there are no edges for the anchor
+    pub start_anchor: Option<BitMatrix>,
+    /// `None` if there are no edges for the anchor
+    pub end_anchor: Option<BitMatrix>,
+    pub final_nodes: BitVector,
+}
+
+/// A path through the graph, as the node it has reached and the position it
+/// took each capture slot at, see [`Graph::captures`]
+type Thread = (usize, Vec<Option<usize>>);
+
+/// The buffers [`Graph::captures`] works in, kept between calls so that
+/// capturing the groups of many matches doesn't allocate for each
+#[derive(Default)]
+pub struct CaptureScratch {
+    threads: Vec<Thread>,
+    next: Vec<Thread>,
+    stack: Vec<Thread>,
+    visited: Vec<bool>,
+    /// the slots of threads that were dropped, to reuse for new ones
+    free: Vec<Vec<Option<usize>>>,
+}
+
+/// returns: a copy of `slots`, in a vector from `free` if there is one
+fn copy_slots(
+    free: &mut Vec<Vec<Option<usize>>>,
+    slots: &[Option<usize>],
+) -> Vec<Option<usize>> {
+    let mut copy = free.pop().unwrap_or_default();
+    copy.clear();
+    copy.extend_from_slice(slots);
+    copy
 }
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -24,15 +79,15 @@ pub struct NodeRef {
     index: usize,
 }
 
-impl PartialEq for Graph {
+impl<T> PartialEq for Graph<T> {
     fn eq(&self, other: &Self) -> bool {
         self.id == other.id
     }
 }
 
-impl Eq for Graph {}
+impl<T> Eq for Graph<T> {}
 
-impl Clone for Graph {
+impl<T: Clone> Clone for Graph<T> {
     fn clone(&self) -> Self {
         Graph {
             nodes: self.nodes.clone(),
@@ -41,24 +96,35 @@ impl Clone for Graph {
     }
 }
 
-impl Graph {
-    pub fn new() -> Graph {
+impl<T: Ord + Clone> Graph<T> {
+    /// returns: a graph with only the initial node, with room for `n` nodes
+    /// before reallocating
+    pub fn with_capacity(n: usize) -> Graph<T> {
+        let mut nodes = Vec::with_capacity(n.max(1));
+        nodes.push(Node::default());
         Graph {
-            nodes: vec![Node::default()],
+            nodes,
             id: GRAPH_ID.fetch_add(1, Ordering::Relaxed),
         }
     }
 
-    fn owns_node(&self, x: NodeRef) -> bool {
-        self.id == x.graph_id
+    /// Like `with_capacity`, with room for the states that `options` expects;
+    /// a graph with more than `max_states` nodes is never compiled, so a
+    /// larger hint would only reserve memory that can't be used, or more
+    /// than there is
+    pub fn for_options(options: &RegexOptions) -> Graph<T> {
+        Graph::with_capacity(
+            options
+                .expected_states
+                .min(options.max_states.saturating_add(1)),
+        )
     }
 
-    fn get_node(&self, x: NodeRef) -> &Node {
-        assert!(self.owns_node(x));
-        &self.nodes[x.index]
+    fn owns_node(&self, x: NodeRef) -> bool {
+        self.id == x.graph_id
     }
 
-    fn get_node_mut(&mut self, x: NodeRef) -> &mut Node {
+    fn get_node_mut(&mut self, x: NodeRef) -> &mut Node<T> {
         assert!(self.owns_node(x));
         &mut self.nodes[x.index]
     }
@@ -70,6 +136,10 @@ impl Graph {
         }
     }
 
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
     pub fn add_node(&mut self) -> NodeRef {
         self.nodes.push(Node::default());
         NodeRef {
@@ -79,39 +149,32 @@ impl Graph {
     }
 
     /// Panics if `x` or `y` doesn't belong to `self`
-    pub fn connect(&mut self, x: NodeRef, y: NodeRef, token: UnicodeCodepoint) {
+    pub fn connect(&mut self, x: NodeRef, y: NodeRef, token: T) {
         assert!(self.owns_node(y));
         self.get_node_mut(x).edges.push((y.index, token));
     }
 
     /// Panics if `x` or `y` doesn't belong to `self`
-    pub fn connect_epsilon(&mut self, x: NodeRef, y: NodeRef) {
+    pub fn connect_anchor(&mut self, x: NodeRef, y: NodeRef, anchor: Anchor) {
         assert!(self.owns_node(y));
-        self.get_node_mut(x).epsilon_edges.push(y.index);
-    }
-
-    /// Panics if `x` doesn't belong to `self`
-    pub fn get_connections(&self, x: NodeRef) -> impl Iterator<Item = NodeRef> {
-        self.get_node(x).edges.iter().map(|(e, _)| NodeRef {
-            graph_id: self.id,
-            index: *e,
-        })
+        self.get_node_mut(x).anchor_edges.push((y.index, anchor));
     }
 
-    /// Panics if `x` doesn't belong to `self`
-    pub fn get_epsilon_connections(
-        &self,
-        x: NodeRef,
-    ) -> impl Iterator<Item = NodeRef> {
-        self.get_node(x).epsilon_edges.iter().map(|e| NodeRef {
-            graph_id: self.id,
-            index: *e,
-        })
+    /// Panics if `x` or `y` doesn't belong to `self`
+    pub fn connect_epsilon(&mut self, x: NodeRef, y: NodeRef) {
+        assert!(self.owns_node(y));
+        self.get_node_mut(x).epsilon_edges.push((y.index, None));
     }
 
-    /// Panics if `x` doesn't belong to `self`
-    pub fn is_final(&self, x: NodeRef) -> bool {
-        self.get_node(x).is_final
+    /// Like `connect_epsilon`, but taking the edge records the current
+    /// position in capture slot `slot`, see [`Graph::captures`]
+    ///
+    /// Panics if `x` or `y` doesn't belong to `self`
+    pub fn connect_tagged(&mut self, x: NodeRef, y: NodeRef, slot: usize) {
+        assert!(self.owns_node(y));
+        self.get_node_mut(x)
+            .epsilon_edges
+            .push((y.index, Some(slot)));
     }
 
     /// Panics if `x` doesn't belong to `self`
@@ -119,58 +182,528 @@ impl Graph {
         self.get_node_mut(x).is_final = true;
     }
 
+    /// returns: every node reachable from `start` through epsilon edges only,
+    /// including `start` itself, in the order they're found
+    ///
+    /// Panics if `start` doesn't belong to `self`
+    pub fn epsilon_closure(&self, start: NodeRef) -> Vec<NodeRef> {
+        assert!(self.owns_node(start));
+        let mut visited = vec![false; self.nodes.len()];
+        visited[start.index] = true;
+        let mut closure = vec![start];
+        let mut stack = vec![start.index];
+        while let Some(a) = stack.pop() {
+            for &(b, _) in &self.nodes[a].epsilon_edges {
+                if !visited[b] {
+                    visited[b] = true;
+                    closure.push(NodeRef {
+                        graph_id: self.id,
+                        index: b,
+                    });
+                    stack.push(b);
+                }
+            }
+        }
+        closure
+    }
+
+    /// Replaces every epsilon edge by copying the edges and finality of the
+    /// nodes it leads to
     pub fn collapse_epsilons(&mut self) {
-        for a in 0..self.nodes.len() {
-            while let Some(b) = self.nodes[a].epsilon_edges.pop() {
-                if a == b {
-                    continue;
+        let closures: Vec<_> = (0..self.nodes.len())
+            .map(|index| {
+                self.epsilon_closure(NodeRef {
+                    graph_id: self.id,
+                    index,
+                })
+            })
+            .collect();
+
+        // edges are copied from the original nodes, which is enough since
+        // each closure is already transitive
+        let original = self.nodes.clone();
+        for (a, closure) in closures.into_iter().enumerate() {
+            for b in closure.into_iter().skip(1) {
+                let b = &original[b.index];
+                let a = &mut self.nodes[a];
+                a.is_final |= b.is_final;
+                a.edges.extend_from_slice(&b.edges);
+                a.anchor_edges.extend_from_slice(&b.anchor_edges);
+            }
+            self.nodes[a].epsilon_edges.clear();
+        }
+    }
+
+    /// Removes every node that can't be reached from the initial node or can't
+    /// reach a final node, since no match goes through them, and numbers the
+    /// rest in the same order. The initial node is always kept.
+    ///
+    /// This invalidates every `NodeRef` into `self`.
+    pub fn remove_unreachable(&mut self) {
+        fn targets<T>(node: &Node<T>) -> impl Iterator<Item = usize> + '_ {
+            let edges = node.edges.iter().map(|(b, _)| *b);
+            let anchor_edges = node.anchor_edges.iter().map(|(b, _)| *b);
+            let epsilon_edges = node.epsilon_edges.iter().map(|(b, _)| *b);
+            edges.chain(anchor_edges).chain(epsilon_edges)
+        }
+
+        fn visit(mut visited: Vec<bool>, next: &[Vec<usize>]) -> Vec<bool> {
+            let mut stack: Vec<_> =
+                (0..visited.len()).filter(|a| visited[*a]).collect();
+            while let Some(a) = stack.pop() {
+                for &b in &next[a] {
+                    if !visited[b] {
+                        visited[b] = true;
+                        stack.push(b);
+                    }
                 }
-                if self.nodes[b].is_final {
-                    self.nodes[a].is_final = true;
+            }
+            visited
+        }
+
+        let n = self.nodes.len();
+        let mut successors = vec![Vec::new(); n];
+        let mut predecessors = vec![Vec::new(); n];
+        for (a, node) in self.nodes.iter().enumerate() {
+            for b in targets(node) {
+                successors[a].push(b);
+                predecessors[b].push(a);
+            }
+        }
+        let mut initial = vec![false; n];
+        initial[0] = true;
+        let reachable = visit(initial, &successors);
+        let finals = self.nodes.iter().map(|node| node.is_final).collect();
+        let live = visit(finals, &predecessors);
+
+        let mut new_index = vec![None; n];
+        let mut count = 0;
+        for a in 0..n {
+            if a == 0 || (reachable[a] && live[a]) {
+                new_index[a] = Some(count);
+                count += 1;
+            }
+        }
+
+        // edges into removed nodes are removed too
+        let renumber =
+            |b: &mut usize| new_index[*b].map(|index| *b = index).is_some();
+        let nodes = core::mem::take(&mut self.nodes);
+        self.nodes = nodes
+            .into_iter()
+            .zip(&new_index)
+            .filter(|(_, index)| index.is_some())
+            .map(|(mut node, _)| {
+                node.edges.retain_mut(|(b, _)| renumber(b));
+                node.anchor_edges.retain_mut(|(b, _)| renumber(b));
+                node.epsilon_edges.retain_mut(|(b, _)| renumber(b));
+                node
+            })
+            .collect();
+        self.id = GRAPH_ID.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Runs the graph over `string[start..end]` without collapsing its
+    /// epsilon edges, and follows the first path that ends in a final node
+    /// at `end`. Paths are ordered by the order the edges were added in, with
+    /// a node's own edges before those reached through its epsilon edges.
+    /// `^` and `$` edges hold at the start and end of all of `string`.
+    ///
+    /// returns: for each of the `slot_count` capture slots, the position the
+    /// path last took an edge tagged with it at, or `None` if no path ends at
+    /// `end`
+    pub fn captures<'a>(
+        &self,
+        scratch: &'a mut CaptureScratch,
+        string: &[T],
+        start: usize,
+        end: usize,
+        slot_count: usize,
+    ) -> Option<&'a [Option<usize>]> {
+        let CaptureScratch {
+            threads,
+            next,
+            stack,
+            visited,
+            free,
+        } = scratch;
+        // the threads of the last call are only kept for their slots
+        free.extend(threads.drain(..).map(|(_, slots)| slots));
+        visited.clear();
+        visited.resize(self.nodes.len(), false);
+
+        let mut slots = free.pop().unwrap_or_default();
+        slots.clear();
+        slots.resize(slot_count, None);
+        stack.push((0, slots));
+        self.add_threads(threads, stack, visited, free, string, start);
+
+        for (token, position) in string[start..end].iter().zip(start + 1..) {
+            visited.fill(false);
+            for (a, slots) in threads.drain(..) {
+                for (b, t) in &self.nodes[a].edges {
+                    if t == token {
+                        stack.push((*b, copy_slots(free, &slots)));
+                        self.add_threads(
+                            next, stack, visited, free, string, position,
+                        );
+                    }
                 }
-                for i in 0..self.nodes[b].edges.len() {
-                    let c = self.nodes[b].edges[i];
-                    self.nodes[a].edges.push(c);
+                free.push(slots);
+            }
+            core::mem::swap(threads, next);
+        }
+
+        threads
+            .iter()
+            .find(|(a, _)| self.nodes[*a].is_final)
+            .map(|(_, slots)| slots.as_slice())
+    }
+
+    /// Adds the threads on `stack`, then every thread reachable from them
+    /// through epsilon edges and anchors that hold at `position`, skipping
+    /// visited nodes
+    fn add_threads(
+        &self,
+        threads: &mut Vec<Thread>,
+        stack: &mut Vec<Thread>,
+        visited: &mut [bool],
+        free: &mut Vec<Vec<Option<usize>>>,
+        string: &[T],
+        position: usize,
+    ) {
+        // a depth-first search with a stack of its own, since a pattern can
+        // chain any number of epsilon edges; the edges of a node are pushed
+        // in reverse, so that they're followed in the order they were added
+        while let Some((a, slots)) = stack.pop() {
+            if visited[a] {
+                free.push(slots);
+                continue;
+            }
+            visited[a] = true;
+
+            let node = &self.nodes[a];
+            for (b, anchor) in node.anchor_edges.iter().rev() {
+                let holds = match anchor {
+                    Anchor::Start => position == 0,
+                    Anchor::End => position == string.len(),
+                };
+                if holds && !visited[*b] {
+                    stack.push((*b, copy_slots(free, &slots)));
                 }
-                for i in 0..self.nodes[b].epsilon_edges.len() {
-                    let c = self.nodes[b].epsilon_edges[i];
-                    self.nodes[a].epsilon_edges.push(c);
+            }
+            for (b, slot) in node.epsilon_edges.iter().rev() {
+                if !visited[*b] {
+                    let mut slots = copy_slots(free, &slots);
+                    if let Some(slot) = slot {
+                        slots[*slot] = Some(position);
+                    }
+                    stack.push((*b, slots));
                 }
             }
+            threads.push((a, slots));
         }
     }
 
-    pub fn compile(&self) -> (HashMap<UnicodeCodepoint, BitMatrix>, BitVector) {
-        let mut token_matrices = HashMap::new();
-        let mut final_nodes = BitVector::new(self.nodes.len());
-
+    /// Returns an error instead of allocating the matrices if there are more
+    /// than `max_states` nodes, or if they'd have more than
+    /// `max_matrix_entries` entries together
+    pub fn compile(
+        &self,
+        max_states: usize,
+        max_matrix_entries: usize,
+    ) -> Result<CompiledGraph<T>, RegexError> {
         let n = self.nodes.len();
+        // every matrix has `n * n` entries
+        let Some(entries) = n.checked_mul(n).filter(|_| n <= max_states) else {
+            return Err(RegexError::AutomatonTooLarge { max_states });
+        };
+
+        let mut token_edges = BTreeMap::<_, Vec<_>>::new();
+        let mut start_edges = Vec::new();
+        let mut end_edges = Vec::new();
+        let mut final_nodes = BitVector::new(n);
 
         for (a_node, a) in self.nodes.iter().zip(0_usize..) {
             if a_node.is_final {
                 final_nodes.set(a, true);
             }
             for (b, token) in &a_node.edges {
-                let matrix = token_matrices
-                    .entry(*token)
-                    .or_insert(BitMatrix::new(n, n));
-                matrix.set(*b, a, true);
+                token_edges.entry(token.clone()).or_default().push((*b, a));
+            }
+            for (b, anchor) in &a_node.anchor_edges {
+                match anchor {
+                    Anchor::Start => start_edges.push((*b, a)),
+                    Anchor::End => end_edges.push((*b, a)),
+                }
             }
         }
 
-        (token_matrices, final_nodes)
+        // tokens often have the same transitions, like every letter of a
+        // case-insensitive literal or the tokens of a class, so those are
+        // only stored once
+        let token_edges: Vec<_> = token_edges
+            .into_iter()
+            .map(|(token, mut edges)| {
+                edges.sort_unstable();
+                edges.dedup();
+                (token, edges)
+            })
+            .collect();
+
+        // the states alone don't bound the memory, since every distinct
+        // transition gets a matrix of its own, so their entries are counted
+        // before allocating any
+        let distinct: BTreeSet<_> =
+            token_edges.iter().map(|(_, edges)| edges).collect();
+        let matrix_count = distinct.len()
+            + usize::from(!start_edges.is_empty())
+            + usize::from(!end_edges.is_empty());
+        if matrix_count
+            .checked_mul(entries)
+            .is_none_or(|total| total > max_matrix_entries)
+        {
+            return Err(RegexError::MatricesTooLarge {
+                max_entries: max_matrix_entries,
+            });
+        }
+
+        let mut matrices = BTreeMap::<Vec<_>, Arc<BitMatrix>>::new();
+        let token_matrices = token_edges
+            .into_iter()
+            .map(|(token, edges)| {
+                let matrix =
+                    matrices.entry(edges).or_insert_with_key(|edges| {
+                        Arc::new(BitMatrix::from_edges(
+                            n,
+                            edges.iter().copied(),
+                        ))
+                    });
+                (token, Arc::clone(matrix))
+            })
+            .collect();
+        let anchor_matrix = |edges: Vec<_>| {
+            (!edges.is_empty()).then(|| BitMatrix::from_edges(n, edges))
+        };
+
+        Ok(CompiledGraph {
+            token_matrices,
+            start_anchor: anchor_matrix(start_edges),
+            end_anchor: anchor_matrix(end_edges),
+            final_nodes,
+        })
     }
+}
 
-    pub fn debug_string(&self) -> String {
-        let mut s = String::new();
-        for (a_node, a) in self.nodes.iter().zip(0_usize..) {
-            for (b, token) in &a_node.edges {
-                s.push_str(&format!("{} {} {}\n", a, b, char::from(*token)));
-            }
-            for b in &a_node.epsilon_edges {
-                s.push_str(&format!("{} {} ε\n", a, b));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utf8::UnicodeCodepoint;
+    use alloc::format;
+    use alloc::string::String;
+
+    /// the graphs the tests build are all over codepoints
+    type Graph = super::Graph<UnicodeCodepoint>;
+
+    impl Graph {
+        fn new() -> Graph {
+            Graph::with_capacity(1)
+        }
+
+        fn get_node(&self, x: NodeRef) -> &Node<UnicodeCodepoint> {
+            assert!(self.owns_node(x));
+            &self.nodes[x.index]
+        }
+
+        fn get_connections(&self, x: NodeRef) -> impl Iterator<Item = NodeRef> {
+            self.get_node(x).edges.iter().map(|(e, _)| NodeRef {
+                graph_id: self.id,
+                index: *e,
+            })
+        }
+
+        fn is_final(&self, x: NodeRef) -> bool {
+            self.get_node(x).is_final
+        }
+
+        fn debug_string(&self) -> String {
+            let mut s = String::new();
+            for (a_node, a) in self.nodes.iter().zip(0_usize..) {
+                for (b, token) in &a_node.edges {
+                    // tokens that would blur the one-edge-per-line format are
+                    // written as their codepoint number
+                    let code = u32::from(*token);
+                    match char::from_u32(code)
+                        .filter(|c| !c.is_control() && !c.is_whitespace())
+                    {
+                        Some(c) => s.push_str(&format!("{} {} {}\n", a, b, c)),
+                        None => {
+                            s.push_str(&format!(
+                                "{} {} U+{:04X}\n",
+                                a, b, code
+                            ));
+                        }
+                    }
+                }
+                for (b, anchor) in &a_node.anchor_edges {
+                    let anchor = match anchor {
+                        Anchor::Start => '^',
+                        Anchor::End => '$',
+                    };
+                    s.push_str(&format!("{} {} {}\n", a, b, anchor));
+                }
+                for (b, slot) in &a_node.epsilon_edges {
+                    match slot {
+                        Some(slot) => {
+                            s.push_str(&format!("{} {} ε{}\n", a, b, slot));
+                        }
+                        None => s.push_str(&format!("{} {} ε\n", a, b)),
+                    }
+                }
             }
+            s
         }
-        s
+    }
+
+    #[test]
+    fn graph_debug_string() {
+        let mut graph = Graph::new();
+        let n0 = graph.get_initial_node();
+        let n1 = graph.add_node();
+        graph.connect(n0, n1, 'a'.into());
+        graph.connect(n0, n1, '\n'.into());
+        graph.connect(n0, n1, ' '.into());
+        graph.connect(n0, n1, UnicodeCodepoint::NUL);
+        graph.connect(n0, n1, '\u{7f}'.into());
+        graph.connect_anchor(n1, n0, Anchor::End);
+        graph.connect_epsilon(n1, n1);
+
+        assert_eq!(
+            graph.debug_string(),
+            "0 1 a\n0 1 U+000A\n0 1 U+0020\n0 1 U+0000\n0 1 U+007F\n\
+             1 0 $\n1 1 ε\n"
+        );
+    }
+
+    #[test]
+    fn graph_epsilon_closure() {
+        let mut graph = Graph::new();
+        let n0 = graph.get_initial_node();
+        let n1 = graph.add_node();
+        let n2 = graph.add_node();
+        let n3 = graph.add_node();
+        let n4 = graph.add_node();
+        // a chain into a cycle, and a self-loop
+        graph.connect_epsilon(n0, n1);
+        graph.connect_epsilon(n1, n2);
+        graph.connect_epsilon(n2, n3);
+        graph.connect_epsilon(n3, n2);
+        graph.connect(n3, n4, 'a'.into());
+        graph.connect_epsilon(n4, n4);
+        graph.set_final(n3);
+
+        let indices = |closure: Vec<NodeRef>| {
+            let mut indices: Vec<_> = closure.iter().map(|x| x.index).collect();
+            indices.sort_unstable();
+            indices
+        };
+        assert_eq!(indices(graph.epsilon_closure(n0)), [0, 1, 2, 3]);
+        assert_eq!(indices(graph.epsilon_closure(n2)), [2, 3]);
+        assert_eq!(indices(graph.epsilon_closure(n4)), [4]);
+        assert_eq!(graph.epsilon_closure(n1)[0].index, 1);
+
+        graph.collapse_epsilons();
+        for x in [n0, n1, n2, n3] {
+            assert!(graph.is_final(x));
+            assert_eq!(
+                graph
+                    .get_connections(x)
+                    .map(|y| y.index)
+                    .collect::<Vec<_>>(),
+                [4]
+            );
+        }
+        assert!(!graph.is_final(n4));
+        assert!(graph.nodes.iter().all(|n| n.epsilon_edges.is_empty()));
+    }
+
+    #[test]
+    fn graph_with_capacity() {
+        for n in [0, 1, 10] {
+            let graph = Graph::with_capacity(n);
+            assert_eq!(graph.node_count(), 1);
+            assert!(graph.nodes.capacity() >= n);
+        }
+    }
+
+    #[test]
+    fn graph_remove_unreachable() {
+        let mut graph = Graph::new();
+        let n0 = graph.get_initial_node();
+        let n1 = graph.add_node();
+        let n2 = graph.add_node();
+        let n3 = graph.add_node();
+        let n4 = graph.add_node();
+        let n5 = graph.add_node();
+        graph.connect(n0, n2, 'a'.into());
+        graph.connect(n2, n4, 'b'.into());
+        graph.connect_anchor(n4, n4, Anchor::End);
+        graph.set_final(n4);
+        // `n1` is final but can't be reached
+        graph.set_final(n1);
+        graph.connect(n1, n4, 'c'.into());
+        // `n3` can be reached but leads nowhere
+        graph.connect(n0, n3, 'a'.into());
+        graph.connect(n3, n3, 'd'.into());
+        // `n5` is neither
+        graph.connect(n5, n5, 'e'.into());
+
+        let before = graph.compile(usize::MAX, usize::MAX).unwrap();
+        graph.remove_unreachable();
+        assert_eq!(graph.node_count(), 3);
+        let after = graph.compile(usize::MAX, usize::MAX).unwrap();
+
+        let tokens = |compiled: &CompiledGraph<UnicodeCodepoint>| {
+            compiled.token_matrices.keys().copied().collect::<Vec<_>>()
+        };
+        assert_eq!(tokens(&before).len(), 5);
+        assert_eq!(tokens(&after), ['a'.into(), 'b'.into()]);
+        assert!(after.token_matrices[&'a'.into()].get(1, 0));
+        assert!(after.token_matrices[&'b'.into()].get(2, 1));
+        assert!(after.end_anchor.unwrap().get(2, 2));
+        assert_eq!(
+            after.final_nodes,
+            BitVector::from_iter([false, false, true])
+        );
+
+        // the initial node stays even if nothing is reachable
+        let mut graph = Graph::new();
+        let n1 = graph.add_node();
+        graph.connect(graph.get_initial_node(), n1, 'a'.into());
+        graph.remove_unreachable();
+        assert_eq!(graph.node_count(), 1);
+        assert!(graph.nodes[0].edges.is_empty());
+    }
+
+    #[test]
+    fn graph_compile_shares_matrices() {
+        let mut graph = Graph::new();
+        let n0 = graph.get_initial_node();
+        let n1 = graph.add_node();
+        graph.connect(n0, n1, 'a'.into());
+        graph.connect(n0, n1, 'b'.into());
+        graph.connect(n1, n1, 'c'.into());
+        graph.set_final(n1);
+
+        let compiled = graph.compile(usize::MAX, usize::MAX).unwrap();
+        let [a, b, c] =
+            ['a', 'b', 'c'].map(|t| &compiled.token_matrices[&t.into()]);
+        // tokens with the same transitions share one matrix
+        assert!(Arc::ptr_eq(a, b));
+        // and a token with other transitions gets its own, with only its
+        // own entries
+        assert!(!Arc::ptr_eq(a, c));
+        assert!(a.get(1, 0) && !a.get(1, 1));
+        assert!(c.get(1, 1) && !c.get(1, 0));
     }
 }
@@ -0,0 +1,163 @@
+use crate::regex::graph::CaptureScratch;
+use crate::regex::{FindScratch, GenericRegex, Match};
+use crate::utf8::UnicodeCodepoint;
+use alloc::vec::Vec;
+
+/// The matches of [`Regex::find_all`], each found once it is asked for;
+/// every method that goes through the matches one after another uses this,
+/// so that they all agree on where the matches are
+///
+/// [`Regex::find_all`]: crate::Regex::find_all
+pub(super) struct Matches<'a, T> {
+    regex: &'a GenericRegex<T>,
+    string: &'a [T],
+    /// where to look for the next match
+    offset: usize,
+    scratch: FindScratch,
+}
+
+impl<T: Ord + Clone> Iterator for Matches<'_, T> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset > self.string.len() {
+            return None;
+        }
+        // the empty pattern matches right where it is, so there's no need to
+        // read the string
+        let next_match = if self.regex.is_empty_pattern() {
+            Some((self.offset, 0))
+        } else {
+            self.regex
+                .find_at(&mut self.scratch, self.string, self.offset)
+        };
+        let Some((start, mut len)) = next_match else {
+            self.offset = self.string.len() + 1;
+            return None;
+        };
+        // a non-empty match that starts at the same index comes first, so
+        // that `a*` finds every `a` rather than the empty match before it
+        if len == 0 && !self.regex.is_empty_pattern() {
+            len = self
+                .regex
+                .shortest_nonempty_match_at(
+                    &mut self.scratch,
+                    self.string,
+                    start,
+                )
+                .unwrap_or(0);
+        }
+        // an empty match would be found again, so step over it
+        self.offset = if len == 0 { start + 1 } else { start + len };
+        Some((start, len))
+    }
+}
+
+/// The iterator returned by [`Regex::split_iter`]
+///
+/// [`Regex::split_iter`]: crate::Regex::split_iter
+pub struct Split<'a, T = UnicodeCodepoint> {
+    matches: Matches<'a, T>,
+    /// the start of the next segment, or `None` once the last segment has
+    /// been returned
+    copied: Option<usize>,
+}
+
+impl<'a, T: Ord + Clone> Iterator for Split<'a, T> {
+    type Item = &'a [T];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let copied = self.copied?;
+        let string = self.matches.string;
+        let Some((start, len)) = self.matches.next() else {
+            self.copied = None;
+            return Some(&string[copied..]);
+        };
+        self.copied = Some(start + len);
+        Some(&string[copied..start])
+    }
+}
+
+/// An iterator over the matches of a regex and their groups, see
+/// [`Regex::captures_find_iter`]
+///
+/// [`Regex::captures_find_iter`]: crate::Regex::captures_find_iter
+pub struct CapturesIter<'a, T = UnicodeCodepoint> {
+    matches: Matches<'a, T>,
+    /// kept between matches, like the vectors of `matches`
+    scratch: CaptureScratch,
+}
+
+impl<T: Ord + Clone> Iterator for CapturesIter<'_, T> {
+    type Item = (Match, Vec<Option<(usize, usize)>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (start, len) = self.matches.next()?;
+        let Matches { regex, string, .. } = self.matches;
+        let groups = regex.captures_at(&mut self.scratch, string, start, len);
+        Some((Match { start, len }, groups))
+    }
+}
+
+impl<T: Ord + Clone> GenericRegex<T> {
+    /// returns: the parts of `string` between matches, like [`str::split`];
+    /// there's always one more part than there are matches, so matches at
+    /// the start or end give empty parts there
+    pub fn split(&self, string: &[T]) -> Vec<Vec<T>> {
+        self.split_iter(string).map(<[_]>::to_vec).collect()
+    }
+
+    /// returns: at most `n` parts of `string` between matches, like
+    /// [`str::splitn`]; the last part is the rest of `string`, including any
+    /// later matches
+    pub fn splitn(&self, string: &[T], n: usize) -> Vec<Vec<T>> {
+        if n == 0 {
+            return Vec::new();
+        }
+        let mut split = self.split_iter(string);
+        let mut parts: Vec<_> =
+            split.by_ref().take(n - 1).map(<[_]>::to_vec).collect();
+        if let Some(copied) = split.copied {
+            parts.push(string[copied..].to_vec());
+        }
+        parts
+    }
+
+    /// Like [`Regex::split`], but finds each match only once the part before
+    /// it is asked for, and borrows the parts from `string`
+    ///
+    /// [`Regex::split`]: crate::Regex::split
+    pub fn split_iter<'a>(&'a self, string: &'a [T]) -> Split<'a, T> {
+        Split {
+            matches: self.matches(string),
+            copied: Some(0),
+        }
+    }
+
+    /// returns: an iterator over the matches that don't overlap, from left to
+    /// right, each with what its groups matched, like [`Regex::captures`];
+    /// each match is only found once it is asked for
+    ///
+    /// [`Regex::captures`]: crate::Regex::captures
+    pub fn captures_find_iter<'a>(
+        &'a self,
+        string: &'a [T],
+    ) -> CapturesIter<'a, T> {
+        CapturesIter {
+            matches: self.matches(string),
+            scratch: CaptureScratch::default(),
+        }
+    }
+
+    /// returns: an iterator over the matches of [`Regex::find_all`]
+    ///
+    /// [`Regex::find_all`]: crate::Regex::find_all
+    pub(super) fn matches<'a>(&'a self, string: &'a [T]) -> Matches<'a, T> {
+        Matches {
+            regex: self,
+            string,
+            offset: 0,
+            scratch: self.find_scratch(),
+        }
+    }
+}
@@ -1,7 +1,14 @@
+//! The syntax tree of a pattern, see [`Regex::parse_ast`]
+//!
+//! This module is public for tools that want to look at a pattern's
+//! structure, but it's hidden from the docs and may change in any release.
+//!
+//! [`Regex::parse_ast`]: crate::Regex::parse_ast
+
 use crate::utf8::{UnicodeCodepoint, Utf8DecodeError, decode_utf8};
 use parsable::{
-    CharLiteral, CharRange, Intersperse, Parsable, RepeatLimited, Span,
-    WithEnd, ZeroPlus,
+    CharLiteral, CharRange, Intersperse, OnePlus, Parsable, Span, WithEnd,
+    ZeroPlus,
 };
 use serde::Serialize;
 
@@ -23,36 +30,109 @@ pub struct ConcatExpr {
 #[derive(Debug, Parsable, Serialize)]
 pub struct KleeneExpr {
     pub atom: Atom,
-    pub star: Option<CharLiteral<b'*'>>,
+    pub quantifier: Option<Quantifier>,
+}
+
+#[derive(Debug, Parsable, Serialize)]
+pub enum Quantifier {
+    Star(CharLiteral<b'*'>),
+    Count {
+        _0: CharLiteral<b'{'>,
+        count: RepeatCount,
+        _1: CharLiteral<b'}'>,
+    },
+}
+
+#[derive(Debug, Parsable, Serialize)]
+pub struct RepeatCount {
+    pub digits: Span<OnePlus<CharRange<b'0', b'9'>>>,
+}
+
+impl RepeatCount {
+    /// returns: the count, or `None` if it doesn't fit in a `usize`
+    pub fn value(&self) -> Option<usize> {
+        self.digits.span.iter().try_fold(0_usize, |acc, digit| {
+            acc.checked_mul(10)?.checked_add(usize::from(digit - b'0'))
+        })
+    }
 }
 
 #[derive(Debug, Parsable, Serialize)]
 pub enum Atom {
-    CharacterAtom(Character),
+    Character(Character),
+    StartAnchor(CharLiteral<b'^'>),
+    EndAnchor(CharLiteral<b'$'>),
     Capture {
         _0: CharLiteral<b'('>,
+        kind: Option<GroupKind>,
         alt: AltExpr,
         _1: CharLiteral<b')'>,
     },
 }
 
+/// What a group does besides grouping, written right after its `(`; a group
+/// without one captures
+#[derive(Debug, Parsable, Serialize)]
+pub enum GroupKind {
+    #[literal = b"?:"]
+    NonCapturing,
+    /// an atomic group, accepted so that patterns from PCRE parse; there's no
+    /// backtracking to cut off, so it's a non-capturing group here, and
+    /// unlike in PCRE, `(?>a*)a` matches `aa`
+    #[literal = b"?>"]
+    Atomic,
+    /// literals inside the group also match their lowercase and uppercase
+    /// forms, like [`RegexOptions::case_insensitive`] for the whole pattern
+    ///
+    /// [`RegexOptions::case_insensitive`]: crate::RegexOptions::case_insensitive
+    #[literal = b"?i:"]
+    CaseInsensitive,
+    /// literals inside the group only match themselves, even in a case
+    /// insensitive pattern
+    #[literal = b"?-i:"]
+    CaseSensitive,
+    /// captures like a group without a kind, and can also be looked up by
+    /// its name
+    Named {
+        _0: NamedGroupStart,
+        name: Span<OnePlus<GroupNameCharacter>>,
+        _1: CharLiteral<b'>'>,
+    },
+}
+
+impl GroupKind {
+    /// returns: whether a group of this kind captures, where `None` is a
+    /// group without a kind
+    pub fn is_capturing(kind: Option<&GroupKind>) -> bool {
+        matches!(kind, None | Some(GroupKind::Named { .. }))
+    }
+}
+
+#[derive(Debug, Parsable, Serialize)]
+pub enum NamedGroupStart {
+    #[literal = b"?P<"]
+    Start,
+}
+
+#[derive(Debug, Parsable, Serialize)]
+pub enum GroupNameCharacter {
+    Digit(CharRange<b'0', b'9'>),
+    Uppercase(CharRange<b'A', b'Z'>),
+    Underscore(CharLiteral<b'_'>),
+    Lowercase(CharRange<b'a', b'z'>),
+}
+
 #[derive(Debug, Parsable, Serialize)]
 pub enum Character {
-    Ascii(Span<AsciiCharacter>),
+    Ascii(AsciiCharacter),
     Unicode(Span<UnicodeCharacter>),
     Escaped(EscapedCharacter),
 }
 
 impl Character {
     pub fn to_codepoint(&self) -> Result<UnicodeCodepoint, Utf8DecodeError> {
-        match self {
-            Character::Ascii(s) => Ok(UnicodeCodepoint::try_from(
-                *s.span
-                    .first()
-                    .expect("ascii character span should not be empty")
-                    as u32,
-            )
-            .expect("ascii character should be a valid unicode codepoint")),
+        let codepoint = match self {
+            Character::Ascii(c) => char::from(c.byte()).into(),
             Character::Unicode(s) => {
                 let s = decode_utf8(&s.span)?;
                 assert_eq!(
@@ -60,36 +140,89 @@ impl Character {
                     1,
                     "single unicode codepoint should be decoded as exactly one codepoint"
                 );
-                Ok(*s.first().unwrap())
+                *s.first().unwrap()
             }
             Character::Escaped(e) => match e {
-                EscapedCharacter::LeftParen => Ok('('.into()),
-                EscapedCharacter::RightParen => Ok(')'.into()),
-                EscapedCharacter::Asterisk => Ok('*'.into()),
-                EscapedCharacter::Backslash => Ok('\\'.into()),
-                EscapedCharacter::VerticalBar => Ok('|'.into()),
+                EscapedCharacter::LeftParen => '('.into(),
+                EscapedCharacter::RightParen => ')'.into(),
+                EscapedCharacter::Asterisk => '*'.into(),
+                EscapedCharacter::Backslash => '\\'.into(),
+                EscapedCharacter::VerticalBar => '|'.into(),
+                EscapedCharacter::Punctuation { c, .. } => {
+                    ascii_codepoint(&c.span)
+                }
             },
-        }
+        };
+        // every codepoint that reaches the graph must be a valid, non-surrogate
+        // codepoint, however it was written in the pattern
+        UnicodeCodepoint::try_from(u32::from(codepoint))
+            .map_err(Utf8DecodeError::UnicodeError)
     }
 }
 
+fn ascii_codepoint(span: &[u8]) -> UnicodeCodepoint {
+    UnicodeCodepoint::try_from(u32::from(
+        *span
+            .first()
+            .expect("ascii character span should not be empty"),
+    ))
+    .expect("ascii character should be a valid unicode codepoint")
+}
+
 #[derive(Debug, Parsable, Serialize)]
 pub enum AsciiCharacter {
-    Ascii1(CharRange<b' ', b'\''>),
+    Ascii1(Span<CharRange<b' ', b'#'>>),
+    // skip $
+    Ascii2(Span<CharRange<b'%', b'\''>>),
     // skip ( ) *
-    Ascii2(CharRange<b'+', b'['>),
+    Ascii3(Span<CharRange<b'+', b'['>>),
     // skip \
-    Ascii3(CharRange<b']', b'{'>),
-    // skip |
-    Ascii4(CharRange<b'}', b'~'>),
+    Ascii4(Span<CharRange<b']', b']'>>),
+    // skip ^
+    Ascii5(Span<CharRange<b'_', b'z'>>),
+    // skip { |
+    Ascii6(Span<CharRange<b'}', b'~'>>),
 }
 
+impl AsciiCharacter {
+    /// returns: the ascii byte of the character
+    pub fn byte(&self) -> u8 {
+        let span = match self {
+            AsciiCharacter::Ascii1(s) => &s.span,
+            AsciiCharacter::Ascii2(s) => &s.span,
+            AsciiCharacter::Ascii3(s) => &s.span,
+            AsciiCharacter::Ascii4(s) => &s.span,
+            AsciiCharacter::Ascii5(s) => &s.span,
+            AsciiCharacter::Ascii6(s) => &s.span,
+        };
+        // a `CharRange` always spans exactly one byte
+        span[0]
+    }
+}
+
+/// A multi-byte UTF-8 sequence, with as many continuation bytes as its
+/// leading byte calls for
 #[derive(Debug, Parsable, Serialize)]
-pub struct UnicodeCharacter {
-    pub b0: CharRange<0b1100_0000, 0b1111_0111>,
-    pub bytes: RepeatLimited<CharRange<0b1000_0000, 0b1011_1111>, 1, 3>,
+pub enum UnicodeCharacter {
+    TwoBytes {
+        b0: CharRange<0b1100_0000, 0b1101_1111>,
+        b1: ContinuationByte,
+    },
+    ThreeBytes {
+        b0: CharRange<0b1110_0000, 0b1110_1111>,
+        b1: ContinuationByte,
+        b2: ContinuationByte,
+    },
+    FourBytes {
+        b0: CharRange<0b1111_0000, 0b1111_0111>,
+        b1: ContinuationByte,
+        b2: ContinuationByte,
+        b3: ContinuationByte,
+    },
 }
 
+pub type ContinuationByte = CharRange<0b1000_0000, 0b1011_1111>;
+
 #[derive(Debug, Parsable, Serialize)]
 pub enum EscapedCharacter {
     #[literal = b"\\("]
@@ -102,4 +235,84 @@ pub enum EscapedCharacter {
     Backslash,
     #[literal = b"\\|"]
     VerticalBar,
+    // any other escaped ascii punctuation is also a literal; `\` followed by
+    // a letter or digit is reserved for named escapes
+    Punctuation {
+        _0: CharLiteral<b'\\'>,
+        c: Span<AsciiPunctuation>,
+    },
+}
+
+#[derive(Debug, Parsable, Serialize)]
+pub enum AsciiPunctuation {
+    Punctuation1(CharRange<b'!', b'/'>),
+    Punctuation2(CharRange<b':', b'@'>),
+    Punctuation3(CharRange<b'[', b'`'>),
+    Punctuation4(CharRange<b'{', b'~'>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_character_byte() {
+        let boundaries = b" #%'+[]_z}~";
+        for b in boundaries {
+            let source = [*b];
+            let mut stream = parsable::ScopedStream::new(&source);
+            let c = AsciiCharacter::parse(&mut stream).unwrap().unwrap();
+            assert_eq!(c.byte(), *b);
+        }
+
+        for b in b"$()*\\^{|" {
+            let source = [*b];
+            let mut stream = parsable::ScopedStream::new(&source);
+            assert!(!matches!(AsciiCharacter::parse(&mut stream), Some(Ok(_))));
+        }
+    }
+
+    #[test]
+    fn ascii_character_alphabet() {
+        fn parses(b: u8) -> bool {
+            let source = [b];
+            let mut stream = parsable::ScopedStream::new(&source);
+            matches!(AsciiCharacter::parse(&mut stream), Some(Ok(_)))
+        }
+
+        // every printable character is a literal, except the metacharacters,
+        // which have to be escaped
+        let metacharacters = b"$()*\\^{|";
+        for b in b' '..=b'~' {
+            assert_eq!(parses(b), !metacharacters.contains(&b), "{b:#04x}");
+        }
+        // control characters, including DEL, aren't
+        for b in (0..b' ').chain([0x7f]) {
+            assert!(!parses(b), "{b:#04x}");
+        }
+
+        for b in metacharacters {
+            let source = [b'\\', *b];
+            let mut stream = parsable::ScopedStream::new(&source);
+            let c = Character::parse(&mut stream).unwrap().unwrap();
+            assert_eq!(u32::from(c.to_codepoint().unwrap()), u32::from(*b));
+        }
+    }
+
+    #[test]
+    fn unicode_character_length() {
+        fn parse(source: &[u8]) -> Option<usize> {
+            let mut stream = parsable::ScopedStream::new(source);
+            let c = Span::<UnicodeCharacter>::parse(&mut stream)?.ok()?;
+            Some(c.span.len())
+        }
+
+        assert_eq!(parse("é".as_bytes()), Some(2));
+        assert_eq!(parse("中".as_bytes()), Some(3));
+        assert_eq!(parse("🔥".as_bytes()), Some(4));
+        // only as many continuation bytes as the leading byte calls for
+        assert_eq!(parse(b"\xc3\xa9\xa9"), Some(2));
+        assert!(parse(b"\xf0\x9f\x94").is_none());
+        assert!(parse(b"\xa9").is_none());
+    }
 }
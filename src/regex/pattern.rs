@@ -0,0 +1,391 @@
+use crate::regex::graph::{Graph, NodeRef};
+use crate::regex::{Anchor, GenericRegex, RegexError, RegexOptions};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A pattern over tokens of any type, already split into its parts, for
+/// [`GenericRegex::from_pattern`]. Each variant is a part of the syntax of a
+/// [`Regex`] pattern. A `Pattern<UnicodeCodepoint>` builds a [`Regex`], which
+/// also works without the `std` feature, where patterns can't be parsed.
+///
+/// [`Regex`]: crate::Regex
+/// [`UnicodeCodepoint`]: crate::UnicodeCodepoint
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Pattern<T> {
+    /// a single token, like `a`
+    Token(T),
+    /// each of the patterns in turn, like `ab`; with none, the empty string
+    Concat(Vec<Pattern<T>>),
+    /// any one of the patterns, like `a|b`; with none, nothing at all
+    Alt(Vec<Pattern<T>>),
+    /// the pattern any number of times, like `a*`
+    Star(Box<Pattern<T>>),
+    /// the pattern exactly `n` times, like `a{n}`
+    Repeat(Box<Pattern<T>>, usize),
+    /// `^`, the start of the input
+    Start,
+    /// `$`, the end of the input
+    End,
+    /// a group that captures what the pattern matched, like `(a)`; groups
+    /// are numbered from 1 in the order they appear
+    Group(Box<Pattern<T>>),
+}
+
+/// returns: the regex matching `pattern`
+pub fn compile<T: Ord + Clone>(
+    pattern: &Pattern<T>,
+    options: &RegexOptions,
+) -> Result<GenericRegex<T>, RegexError> {
+    // checked before compiling, since the graph grows with every branch
+    if branches_in(pattern) > options.max_alternation_branches {
+        return Err(RegexError::TooManyBranches {
+            max: options.max_alternation_branches,
+        });
+    }
+    compile_with_names(pattern, BTreeMap::new(), options)
+}
+
+/// Like [`compile`], with `group_names` looking up groups by name, but
+/// without checking the number of branches, which is up to the caller. A
+/// parsed pattern is compiled this way after it's lowered to a [`Pattern`],
+/// since its case insensitive literals become alternations that shouldn't
+/// count as branches.
+pub fn compile_with_names<T: Ord + Clone>(
+    pattern: &Pattern<T>,
+    group_names: BTreeMap<String, usize>,
+    options: &RegexOptions,
+) -> Result<GenericRegex<T>, RegexError> {
+    let mut compiler = Compiler::new(options, false);
+    compiler.add_root(pattern)?;
+    let group_count = compiler.group_count;
+
+    // groups are tracked on a separate graph that keeps its epsilon edges, so
+    // that they don't cost anything when only matching
+    let capture_graph = if group_count > 0 {
+        let mut compiler = Compiler::new(options, true);
+        compiler.add_root(pattern)?;
+        Some(compiler.graph)
+    } else {
+        None
+    };
+
+    GenericRegex::from_graphs(
+        compiler.graph,
+        capture_graph,
+        group_count,
+        group_names,
+        options,
+    )
+}
+
+struct Compiler<'a, T> {
+    graph: Graph<T>,
+    options: &'a RegexOptions,
+    /// whether group boundaries are added as tagged epsilon edges, see
+    /// [`Graph::captures`]
+    tagged: bool,
+    /// the number of groups added so far
+    group_count: usize,
+}
+
+impl<'a, T: Ord + Clone> Compiler<'a, T> {
+    fn new(options: &'a RegexOptions, tagged: bool) -> Compiler<'a, T> {
+        Compiler {
+            graph: Graph::for_options(options),
+            options,
+            tagged,
+            group_count: 0,
+        }
+    }
+
+    fn add_root(&mut self, pattern: &Pattern<T>) -> Result<(), RegexError> {
+        let start_node = self.graph.get_initial_node();
+        let final_node = self.graph.add_node();
+        self.graph.set_final(final_node);
+        self.add(start_node, final_node, pattern)
+    }
+
+    fn add(
+        &mut self,
+        start: NodeRef,
+        end: NodeRef,
+        pattern: &Pattern<T>,
+    ) -> Result<(), RegexError> {
+        match pattern {
+            Pattern::Token(token) => {
+                self.graph.connect(start, end, token.clone());
+            }
+            Pattern::Concat(parts) => {
+                let mut prev = start;
+                for part in parts {
+                    prev = self.add_part(prev, part)?;
+                }
+                if prev != end {
+                    self.graph.connect_epsilon(prev, end);
+                }
+            }
+            Pattern::Alt(alts) => {
+                let separate = self.tagged && alts.len() > 1;
+                for alt in alts {
+                    if separate {
+                        // every alternative gets its own epsilon edge, so
+                        // that they are tried in order when capturing
+                        let alt_start = self.graph.add_node();
+                        self.graph.connect_epsilon(start, alt_start);
+                        self.add(alt_start, end, alt)?;
+                    } else {
+                        self.add(start, end, alt)?;
+                    }
+                }
+            }
+            Pattern::Star(_) | Pattern::Repeat(..) => {
+                let last = self.add_part(start, pattern)?;
+                self.graph.connect_epsilon(last, end);
+            }
+            Pattern::Start => {
+                self.graph.connect_anchor(start, end, Anchor::Start);
+            }
+            Pattern::End => self.graph.connect_anchor(start, end, Anchor::End),
+            Pattern::Group(pattern) => {
+                self.group_count += 1;
+                if self.tagged {
+                    // group `i` records where it starts in slot `2 * i` and
+                    // where it ends in slot `2 * i + 1`
+                    let group = self.group_count;
+                    let group_start = self.graph.add_node();
+                    let group_end = self.graph.add_node();
+                    self.graph.connect_tagged(start, group_start, 2 * group);
+                    self.add(group_start, group_end, pattern)?;
+                    self.graph.connect_tagged(group_end, end, 2 * group + 1);
+                } else {
+                    self.add(start, end, pattern)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds `part` of a concatenation after `prev`
+    ///
+    /// returns: the node that the rest of the concatenation starts at
+    fn add_part(
+        &mut self,
+        prev: NodeRef,
+        part: &Pattern<T>,
+    ) -> Result<NodeRef, RegexError> {
+        match part {
+            Pattern::Star(pattern) => {
+                // the loop gets a node of its own, since a loop on `prev`
+                // could be entered again after a later part that also starts
+                // at `prev`, like the `a` after the `b` in `a*b*`
+                let next = self.graph.add_node();
+                self.graph.connect_epsilon(prev, next);
+                self.add(next, next, pattern)?;
+                Ok(next)
+            }
+            Pattern::Repeat(pattern, count) => {
+                // every repetition is a copy of the pattern, so the bound has
+                // to be checked before expanding it
+                if *count > self.options.max_repeat {
+                    return Err(RegexError::RepeatTooLarge {
+                        max: self.options.max_repeat,
+                    });
+                }
+                // every copy has the same group numbers
+                let first_group = self.group_count;
+                let mut prev = prev;
+                for _ in 0..*count {
+                    self.group_count = first_group;
+                    let next = self.graph.add_node();
+                    self.add(prev, next, pattern)?;
+                    prev = next;
+                    // nested repetitions multiply, so give up as soon as the
+                    // graph is too large rather than when compiling it
+                    if self.graph.node_count() > self.options.max_states {
+                        return Err(RegexError::AutomatonTooLarge {
+                            max_states: self.options.max_states,
+                        });
+                    }
+                }
+                self.group_count = first_group + groups_in(pattern);
+                Ok(prev)
+            }
+            _ => {
+                let next = self.graph.add_node();
+                self.add(prev, next, part)?;
+                Ok(next)
+            }
+        }
+    }
+}
+
+/// returns: the number of alternatives of every [`Pattern::Alt`] in `pattern`
+/// together, including `pattern` itself
+fn branches_in<T>(pattern: &Pattern<T>) -> usize {
+    match pattern {
+        Pattern::Token(_) | Pattern::Start | Pattern::End => 0,
+        Pattern::Concat(parts) => parts.iter().map(branches_in).sum(),
+        Pattern::Alt(alts) => {
+            alts.len() + alts.iter().map(branches_in).sum::<usize>()
+        }
+        Pattern::Star(pattern)
+        | Pattern::Repeat(pattern, _)
+        | Pattern::Group(pattern) => branches_in(pattern),
+    }
+}
+
+/// returns: the number of groups in `pattern`, including `pattern` itself
+fn groups_in<T>(pattern: &Pattern<T>) -> usize {
+    match pattern {
+        Pattern::Token(_) | Pattern::Start | Pattern::End => 0,
+        Pattern::Concat(parts) | Pattern::Alt(parts) => {
+            parts.iter().map(groups_in).sum()
+        }
+        Pattern::Star(pattern) | Pattern::Repeat(pattern, _) => {
+            groups_in(pattern)
+        }
+        Pattern::Group(pattern) => 1 + groups_in(pattern),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    /// the tokens of a small lexer, for matching over token streams
+    #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+    enum Lexeme {
+        Ident,
+        Number,
+        Comma,
+        LParen,
+        RParen,
+    }
+
+    #[test]
+    fn pattern_lexemes() {
+        use Lexeme::*;
+        use Pattern::{Alt, Concat, Group, Star, Token};
+
+        // a call with at least one argument, like `f(x, 1)`
+        let arg = || Alt(Vec::from([Token(Ident), Token(Number)]));
+        let call = Concat(Vec::from([
+            Token(Ident),
+            Token(LParen),
+            Group(Box::new(arg())),
+            Star(Box::new(Concat(Vec::from([Token(Comma), arg()])))),
+            Token(RParen),
+        ]));
+        let regex = GenericRegex::from_pattern(&call).unwrap();
+
+        assert!(regex.test(&[Ident, LParen, Number, RParen]));
+        assert!(regex.test(&[Ident, LParen, Number, Comma, Ident, RParen]));
+        assert!(!regex.test(&[Ident, LParen, RParen]));
+        assert!(!regex.test(&[Ident, LParen, Number, Comma, RParen]));
+
+        let tokens = [Number, Comma, Ident, LParen, Ident, RParen, Comma];
+        assert_eq!(regex.find(&tokens), Some((2, 4)));
+        assert_eq!(
+            regex.captures(&tokens),
+            Some(vec![Some((2, 4)), Some((4, 1))])
+        );
+        assert_eq!(
+            regex.alphabet().collect::<Vec<_>>(),
+            [Ident, Number, Comma, LParen, RParen]
+        );
+    }
+
+    #[test]
+    fn pattern_too_many_branches() {
+        use Pattern::{Alt, Concat, Star, Token};
+
+        let alt = |n: u32| Alt((0..n).map(Token).collect());
+        assert!(GenericRegex::from_pattern(&alt(10_000)).is_ok());
+        assert!(matches!(
+            GenericRegex::from_pattern(&alt(10_001)),
+            Err(RegexError::TooManyBranches { max: 10_000 })
+        ));
+
+        // the branches of nested alternations add up
+        let nested =
+            Concat(Vec::from([alt(5_000), Star(Box::new(alt(5_001)))]));
+        assert!(matches!(
+            GenericRegex::from_pattern(&nested),
+            Err(RegexError::TooManyBranches { max: 10_000 })
+        ));
+    }
+
+    #[test]
+    fn pattern_with_options() {
+        use crate::RegexOptions;
+        use Pattern::{Alt, Repeat, Token};
+
+        let options = RegexOptions {
+            max_repeat: 3,
+            max_alternation_branches: 2,
+            ..RegexOptions::default()
+        };
+        let repeat = |n| Repeat(Box::new(Token(1)), n);
+        let regex =
+            GenericRegex::from_pattern_with_options(&repeat(3), &options);
+        assert!(regex.unwrap().test(&[1, 1, 1]));
+        assert!(matches!(
+            GenericRegex::from_pattern_with_options(&repeat(4), &options),
+            Err(RegexError::RepeatTooLarge { max: 3 })
+        ));
+        assert!(GenericRegex::from_pattern(&repeat(4)).is_ok());
+
+        let alt = Alt(Vec::from([Token(1), Token(2), Token(3)]));
+        assert!(matches!(
+            GenericRegex::from_pattern_with_options(&alt, &options),
+            Err(RegexError::TooManyBranches { max: 2 })
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn pattern_like_parsed() {
+        use crate::{Regex, UnicodeCodepoint};
+        use Pattern::{Alt, Concat, End, Group, Repeat, Star, Start, Token};
+
+        let c = |c: char| Token(UnicodeCodepoint::from(c));
+        let cases = [
+            (Concat(Vec::new()), ""),
+            (Alt(Vec::new()), "$a"),
+            (Concat(Vec::from([c('a'), Star(Box::new(c('b')))])), "ab*"),
+            (
+                Alt(Vec::from([
+                    Concat(Vec::from([Start, c('a')])),
+                    Concat(Vec::from([c('b'), End])),
+                ])),
+                "^a|b$",
+            ),
+            (Repeat(Box::new(Group(Box::new(c('a')))), 2), "(a){2}"),
+            (Star(Box::new(Group(Box::new(Concat(Vec::new()))))), "()*"),
+        ];
+        let strings = ["", "a", "b", "ab", "abb", "aa", "ba", "xab", "aab"];
+        for (pattern, source) in cases {
+            let from_pattern = Regex::from_pattern(&pattern).unwrap();
+            let parsed = Regex::new(source.as_bytes()).unwrap();
+            assert_eq!(from_pattern.group_count(), parsed.group_count());
+            for s in strings {
+                let string: Vec<_> =
+                    s.chars().map(UnicodeCodepoint::from).collect();
+                assert_eq!(
+                    from_pattern.captures(&string),
+                    parsed.captures(&string),
+                    "{source:?} on {s:?}"
+                );
+            }
+        }
+
+        let too_many = Repeat(Box::new(c('a')), 1001);
+        assert!(matches!(
+            Regex::from_pattern(&too_many),
+            Err(RegexError::RepeatTooLarge { max: 1000 })
+        ));
+    }
+}
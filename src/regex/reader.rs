@@ -0,0 +1,153 @@
+use crate::regex::Regex;
+use crate::utf8::Utf8DecodeError;
+use alloc::vec;
+use alloc::vec::Vec;
+
+impl Regex {
+    /// Like [`Regex::test`], but reads and decodes the string from `reader`
+    /// a chunk at a time, so it doesn't have to be in memory all at once.
+    /// Reading stops as soon as no state is left.
+    ///
+    /// returns: whether all of the input matches the regex, or an error of
+    /// kind [`std::io::ErrorKind::InvalidData`] if it isn't valid UTF-8
+    pub fn test_reader<R: std::io::Read>(
+        &self,
+        mut reader: R,
+    ) -> std::io::Result<bool> {
+        let mut error = None;
+        let mut buffer = vec![0; 8192];
+        // the start of a codepoint that the last read cut off
+        let mut carry = 0;
+        let mut decoded = Vec::new();
+        let mut next = 0;
+        let string = core::iter::from_fn(|| {
+            loop {
+                if let Some(c) = decoded.get(next) {
+                    next += 1;
+                    return Some(*c);
+                }
+                if error.is_some() {
+                    return None;
+                }
+                let read = match reader.read(&mut buffer[carry..]) {
+                    Ok(0) if carry == 0 => return None,
+                    Ok(0) => {
+                        error = Some(invalid_data(
+                            Utf8DecodeError::UnexpectedEndOfStream,
+                        ));
+                        return None;
+                    }
+                    Ok(read) => read,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {
+                        continue;
+                    }
+                    Err(e) => {
+                        error = Some(e);
+                        return None;
+                    }
+                };
+                let filled = carry + read;
+                let complete = complete_utf8_len(&buffer[..filled]);
+                decoded.clear();
+                next = 0;
+                for c in crate::utf8::decode_utf8_iter(&buffer[..complete]) {
+                    match c {
+                        Ok(c) => decoded.push(c),
+                        Err(e) => error = Some(invalid_data(e)),
+                    }
+                }
+                buffer.copy_within(complete..filled, 0);
+                carry = filled - complete;
+            }
+        });
+        let matched = self.test_iter(string);
+        match error {
+            Some(e) => Err(e),
+            None => Ok(matched),
+        }
+    }
+}
+
+/// returns: the length of the longest prefix of `utf8` that doesn't end in
+/// the middle of a codepoint; invalid bytes count as complete, so that
+/// decoding them fails
+fn complete_utf8_len(utf8: &[u8]) -> usize {
+    for (i, b) in utf8.iter().enumerate().rev().take(3) {
+        // continuation bytes look like `10xxxxxx`
+        if b >> 6 == 0b10 {
+            continue;
+        }
+        let len = match b.leading_ones() {
+            0 => 1,
+            n @ 2..=4 => n as usize,
+            _ => return utf8.len(),
+        };
+        return if i + len > utf8.len() { i } else { utf8.len() };
+    }
+    utf8.len()
+}
+
+fn invalid_data(e: Utf8DecodeError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utf8;
+
+    #[test]
+    fn regex_test_reader() {
+        /// returns `chunk` bytes at a time
+        struct Chunked<'a> {
+            bytes: &'a [u8],
+            chunk: usize,
+            reads: usize,
+        }
+
+        impl std::io::Read for Chunked<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                let len = self.chunk.min(buf.len()).min(self.bytes.len());
+                buf[..len].copy_from_slice(&self.bytes[..len]);
+                self.bytes = &self.bytes[len..];
+                self.reads += 1;
+                Ok(len)
+            }
+        }
+
+        let regex = Regex::new("(åb|🔥)*c$".as_bytes()).unwrap();
+        for s in ["c", "åbc", "🔥åb🔥c", "", "åb", "åbcc", "🔥x"] {
+            let string = utf8::decode_utf8(s.as_bytes()).unwrap();
+            for chunk in [1, 2, 3, 5, 100] {
+                let mut reader = Chunked {
+                    bytes: s.as_bytes(),
+                    chunk,
+                    reads: 0,
+                };
+                let matched = regex.test_reader(&mut reader).unwrap();
+                assert_eq!(matched, regex.test(&string), "{s:?} {chunk}");
+            }
+        }
+
+        // codepoints cut off by the end of the input, or invalid ones
+        for bytes in [&b"\xc3\xa5b\xc3"[..], b"\xff", b"\xf0\x9f\x94c"] {
+            let reader = Chunked {
+                bytes,
+                chunk: 2,
+                reads: 0,
+            };
+            let e = regex.test_reader(reader).unwrap_err();
+            assert_eq!(e.kind(), std::io::ErrorKind::InvalidData, "{bytes:?}");
+        }
+
+        // nothing is read past the `x`
+        let bytes = [&b"c"[..], &[b'x'; 100_000]].concat();
+        let mut reader = Chunked {
+            bytes: &bytes,
+            chunk: 1,
+            reads: 0,
+        };
+        assert!(!regex.test_reader(&mut reader).unwrap());
+        assert_eq!(reader.reads, 2);
+    }
+}
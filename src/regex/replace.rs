@@ -0,0 +1,189 @@
+use crate::regex::graph::CaptureScratch;
+use crate::regex::{GenericRegex, Regex};
+use crate::utf8::UnicodeCodepoint;
+use alloc::vec::Vec;
+
+impl<T: Ord + Clone> GenericRegex<T> {
+    /// returns: `string` with the first match, if any, replaced by
+    /// `replacement`
+    pub fn replace(&self, string: &[T], replacement: &[T]) -> Vec<T> {
+        let Some((start, len)) = self.find(string) else {
+            return string.to_vec();
+        };
+        let mut out =
+            Vec::with_capacity(string.len() - len + replacement.len());
+        out.extend_from_slice(&string[..start]);
+        out.extend_from_slice(replacement);
+        out.extend_from_slice(&string[start + len..]);
+        out
+    }
+
+    /// returns: `string` with the first `n` non-overlapping matches replaced
+    /// by `replacement`, like [`str::replacen`]
+    pub fn replacen(
+        &self,
+        string: &[T],
+        replacement: &[T],
+        n: usize,
+    ) -> Vec<T> {
+        let mut out = Vec::with_capacity(string.len());
+        let mut copied = 0;
+        for (start, len) in self.matches(string).take(n) {
+            out.extend_from_slice(&string[copied..start]);
+            out.extend_from_slice(replacement);
+            copied = start + len;
+        }
+        out.extend_from_slice(&string[copied..]);
+        out
+    }
+}
+
+impl Regex {
+    /// Like [`Regex::replace`], but replaces every match, and `template` can
+    /// refer to the groups of each match: `$n` is the text of group `n`
+    /// (`$0` is the whole match), and `$$` is a `$`. Groups that didn't take
+    /// part in the match, or don't exist, are replaced by nothing. A `$`
+    /// followed by anything else is kept as is.
+    ///
+    /// returns: `string` with every non-overlapping match replaced by
+    /// `template`, with its group references expanded
+    pub fn replace_all_template(
+        &self,
+        string: &[UnicodeCodepoint],
+        template: &[UnicodeCodepoint],
+    ) -> Vec<UnicodeCodepoint> {
+        let template = parse_template(template);
+        let mut out = Vec::with_capacity(string.len());
+        let mut copied = 0;
+        let mut scratch = CaptureScratch::default();
+        for (start, len) in self.matches(string) {
+            let groups = self.captures_at(&mut scratch, string, start, len);
+            out.extend_from_slice(&string[copied..start]);
+            for piece in &template {
+                match piece {
+                    TemplatePiece::Literal(c) => out.push(*c),
+                    TemplatePiece::Group(n) => {
+                        if let Some(Some((start, len))) = groups.get(*n) {
+                            out.extend_from_slice(
+                                &string[*start..*start + *len],
+                            );
+                        }
+                    }
+                }
+            }
+            copied = start + len;
+        }
+        out.extend_from_slice(&string[copied..]);
+        out
+    }
+}
+
+enum TemplatePiece {
+    Literal(UnicodeCodepoint),
+    Group(usize),
+}
+
+/// returns: `template` split into literals and group references, see
+/// [`Regex::replace_all_template`]
+fn parse_template(template: &[UnicodeCodepoint]) -> Vec<TemplatePiece> {
+    let dollar = UnicodeCodepoint::from('$');
+    let digit = |c: &UnicodeCodepoint| char::from(*c).to_digit(10);
+
+    let mut pieces = Vec::new();
+    let mut i = 0;
+    while i < template.len() {
+        let c = template[i];
+        i += 1;
+        if c != dollar {
+            pieces.push(TemplatePiece::Literal(c));
+        } else if template.get(i) == Some(&dollar) {
+            pieces.push(TemplatePiece::Literal(dollar));
+            i += 1;
+        } else if template.get(i).and_then(digit).is_some() {
+            // too large a number refers to a group that doesn't exist
+            let mut group = Some(0_usize);
+            while let Some(d) = template.get(i).and_then(digit) {
+                group = group
+                    .and_then(|g| g.checked_mul(10))
+                    .and_then(|g| g.checked_add(d as usize));
+                i += 1;
+            }
+            pieces.push(TemplatePiece::Group(group.unwrap_or(usize::MAX)));
+        } else {
+            pieces.push(TemplatePiece::Literal(dollar));
+        }
+    }
+    pieces
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::utf8;
+
+    #[test]
+    fn regex_replace() {
+        fn replace(r: &str, s: &str, replacement: &str) -> String {
+            let replaced = Regex::new(r.as_bytes()).unwrap().replace(
+                &utf8::decode_utf8(s.as_bytes()).unwrap(),
+                &utf8::decode_utf8(replacement.as_bytes()).unwrap(),
+            );
+            utf8::encode_utf8_string(&replaced)
+        }
+
+        assert_eq!(replace("a", "banana", "o"), "bonana");
+        assert_eq!(replace("an*a", "banana", "🔥"), "b🔥na");
+        assert_eq!(replace("x", "banana", "o"), "banana");
+        assert_eq!(replace("b", "banana", ""), "anana");
+        assert_eq!(replace("a*", "banana", "c"), "cbanana");
+        assert_eq!(replace("a$", "banana", "!"), "banan!");
+    }
+
+    #[test]
+    fn regex_replacen() {
+        fn replacen(r: &str, s: &str, replacement: &str, n: usize) -> String {
+            let replaced = Regex::new(r.as_bytes()).unwrap().replacen(
+                &utf8::decode_utf8(s.as_bytes()).unwrap(),
+                &utf8::decode_utf8(replacement.as_bytes()).unwrap(),
+                n,
+            );
+            utf8::encode_utf8_string(&replaced)
+        }
+
+        assert_eq!(replacen("a", "banana", "o", 2), "bonona");
+        assert_eq!(replacen("a", "banana", "o", 0), "banana");
+        assert_eq!(replacen("a", "banana", "o", 1), "bonana");
+        assert_eq!(replacen("a", "banana", "o", 10), "bonono");
+        assert_eq!(replacen("an", "banana", "", 2), "ba");
+        assert_eq!(replacen("a*", "ba", "-", 2), "-b-");
+        assert_eq!(replacen("a*", "ba", "-", 10), "-b--");
+        assert_eq!(replacen("^a", "aaa", "b", 2), "baa");
+    }
+
+    #[test]
+    fn regex_replace_all_template() {
+        fn replace(r: &str, s: &str, template: &str) -> String {
+            let replaced =
+                Regex::new(r.as_bytes()).unwrap().replace_all_template(
+                    &utf8::decode_utf8(s.as_bytes()).unwrap(),
+                    &utf8::decode_utf8(template.as_bytes()).unwrap(),
+                );
+            utf8::encode_utf8_string(&replaced)
+        }
+
+        assert_eq!(replace("(a)(b)", "abxab", "$2$1"), "baxba");
+        assert_eq!(replace("(a)(b)", "ab", "<$0>"), "<ab>");
+        assert_eq!(replace("a", "banana", "$$"), "b$n$n$");
+        // groups that didn't take part, or don't exist
+        assert_eq!(replace("(a)|b", "ab", "[$1]"), "[a][]");
+        assert_eq!(replace("(a)", "a", "$3$99999999999999999999"), "");
+        assert_eq!(replace("a", "a", "$x$"), "$x$");
+        assert_eq!(replace("x", "banana", "$0"), "banana");
+        // empty matches
+        assert_eq!(replace("", "ab", "-"), "-a-b-");
+        assert_eq!(replace("^", "ab", "-"), "-ab");
+        // a non-empty match comes before the empty one at the same index
+        assert_eq!(replace("a*", "aba", "-"), "--b--");
+        assert_eq!(replace("a*b", "aab", "-"), "-");
+    }
+}
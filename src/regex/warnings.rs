@@ -0,0 +1,219 @@
+use crate::regex::parse::{
+    AltExpr, Atom, Character, ConcatExpr, GroupKind, KleeneExpr, Quantifier,
+};
+use alloc::vec::Vec;
+
+/// A part of a pattern that compiles, but likely doesn't do what was meant,
+/// see [`Regex::new_with_warnings`]
+///
+/// [`Regex::new_with_warnings`]: crate::Regex::new_with_warnings
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Warning {
+    /// a `*` on an expression that already matches the empty string, like
+    /// `(a*)*`, which matches the same as `a*`
+    NullableStar,
+    /// an alternative that is the same as an earlier one in the same
+    /// alternation, like the second `a` in `a|a`, so it never adds a match
+    DuplicateAlternative,
+}
+
+impl core::fmt::Display for Warning {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Warning::NullableStar => write!(
+                f,
+                "'*' on an expression that already matches the empty string"
+            ),
+            Warning::DuplicateAlternative => {
+                write!(f, "alternative is the same as an earlier one")
+            }
+        }
+    }
+}
+
+/// returns: the warnings for `alt`, in the order their parts appear in the
+/// pattern
+pub fn warnings(alt: &AltExpr) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    add_alt_warnings(alt, &mut warnings);
+    warnings
+}
+
+fn add_alt_warnings(alt: &AltExpr, warnings: &mut Vec<Warning>) {
+    let alts = &alt.alts.nodes;
+    for (i, a) in alts.iter().enumerate() {
+        if alts[..i].iter().any(|b| concat_eq(a, b)) {
+            warnings.push(Warning::DuplicateAlternative);
+        }
+        for p in &a.parts.nodes {
+            if matches!(p.quantifier, Some(Quantifier::Star(_)))
+                && atom_nullable(&p.atom)
+            {
+                warnings.push(Warning::NullableStar);
+            }
+            if let Atom::Capture { alt, .. } = &p.atom {
+                add_alt_warnings(alt, warnings);
+            }
+        }
+    }
+}
+
+/// returns: whether `a` and `b` are written the same way, wherever they are
+/// in the pattern; the syntax tree has no `PartialEq`, since the parts that
+/// come from `parsable` don't have one
+fn alt_eq(a: &AltExpr, b: &AltExpr) -> bool {
+    let (a, b) = (&a.alts.nodes, &b.alts.nodes);
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| concat_eq(a, b))
+}
+
+fn concat_eq(a: &ConcatExpr, b: &ConcatExpr) -> bool {
+    let (a, b) = (&a.parts.nodes, &b.parts.nodes);
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| kleene_eq(a, b))
+}
+
+fn kleene_eq(a: &KleeneExpr, b: &KleeneExpr) -> bool {
+    let quantifiers_eq = match (&a.quantifier, &b.quantifier) {
+        (None, None)
+        | (Some(Quantifier::Star(_)), Some(Quantifier::Star(_))) => true,
+        (
+            Some(Quantifier::Count { count: a, .. }),
+            Some(Quantifier::Count { count: b, .. }),
+        ) => a.digits.span == b.digits.span,
+        _ => false,
+    };
+    quantifiers_eq && atom_eq(&a.atom, &b.atom)
+}
+
+fn atom_eq(a: &Atom, b: &Atom) -> bool {
+    match (a, b) {
+        (Atom::Character(a), Atom::Character(b)) => character_eq(a, b),
+        (Atom::StartAnchor(_), Atom::StartAnchor(_))
+        | (Atom::EndAnchor(_), Atom::EndAnchor(_)) => true,
+        (
+            Atom::Capture {
+                kind: a_kind,
+                alt: a,
+                ..
+            },
+            Atom::Capture {
+                kind: b_kind,
+                alt: b,
+                ..
+            },
+        ) => group_kind_eq(a_kind.as_ref(), b_kind.as_ref()) && alt_eq(a, b),
+        _ => false,
+    }
+}
+
+/// returns: whether `a` and `b` are the same codepoint, written the same way,
+/// so that an escaped `\@` is different from `@`
+fn character_eq(a: &Character, b: &Character) -> bool {
+    let written_alike = matches!(
+        (a, b),
+        (Character::Ascii(_), Character::Ascii(_))
+            | (Character::Unicode(_), Character::Unicode(_))
+            | (Character::Escaped(_), Character::Escaped(_))
+    );
+    match (a.to_codepoint(), b.to_codepoint()) {
+        (Ok(a), Ok(b)) => written_alike && a == b,
+        _ => false,
+    }
+}
+
+fn group_kind_eq(a: Option<&GroupKind>, b: Option<&GroupKind>) -> bool {
+    match (a, b) {
+        (
+            Some(GroupKind::Named { name: a, .. }),
+            Some(GroupKind::Named { name: b, .. }),
+        ) => a.span == b.span,
+        (Some(a), Some(b)) => {
+            core::mem::discriminant(a) == core::mem::discriminant(b)
+        }
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+/// returns: whether `alt` matches the empty string somewhere, counting
+/// anchors as matching it
+fn alt_nullable(alt: &AltExpr) -> bool {
+    alt.alts.nodes.iter().any(concat_nullable)
+}
+
+fn concat_nullable(concat: &ConcatExpr) -> bool {
+    concat.parts.nodes.iter().all(kleene_nullable)
+}
+
+fn kleene_nullable(kleene: &KleeneExpr) -> bool {
+    match &kleene.quantifier {
+        Some(Quantifier::Star(_)) => true,
+        Some(Quantifier::Count { count, .. }) if count.value() == Some(0) => {
+            true
+        }
+        _ => atom_nullable(&kleene.atom),
+    }
+}
+
+fn atom_nullable(atom: &Atom) -> bool {
+    match atom {
+        Atom::Character(_) => false,
+        Atom::StartAnchor(_) | Atom::EndAnchor(_) => true,
+        Atom::Capture { alt, .. } => alt_nullable(alt),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Regex;
+
+    #[test]
+    fn warnings_alt_eq() {
+        fn eq(a: &str, b: &str) -> bool {
+            let a = Regex::parse_ast(a.as_bytes()).unwrap();
+            let b = Regex::parse_ast(b.as_bytes()).unwrap();
+            alt_eq(&a.root.node, &b.root.node)
+        }
+
+        let same = [
+            "", "a", "ab|c", "a*", "a{02}", "^a$", "(a)", "(?:a)", "(?i:a)",
+            "(?P<x>a)", "\\(", "🔥", "\\@",
+        ];
+        for r in same {
+            assert!(eq(r, r), "{r:?}");
+        }
+        let different = [
+            ("a", "b"),
+            ("a", "aa"),
+            ("a|b", "b|a"),
+            ("a*", "a"),
+            ("a{2}", "a{3}"),
+            ("a{2}", "a{02}"),
+            ("^", "$"),
+            ("(a)", "(?:a)"),
+            ("(?:a)", "(?>a)"),
+            ("(?P<x>a)", "(?P<y>a)"),
+            ("(?P<x>a)", "(a)"),
+            ("\\@", "@"),
+            ("🔥", "🔥🔥"),
+        ];
+        for (a, b) in different {
+            assert!(!eq(a, b), "{a:?} {b:?}");
+            assert!(!eq(b, a), "{b:?} {a:?}");
+        }
+    }
+
+    #[test]
+    fn warnings_nullable() {
+        fn nullable(r: &str) -> bool {
+            let ast = Regex::parse_ast(r.as_bytes()).unwrap();
+            alt_nullable(&ast.root.node)
+        }
+
+        for r in ["", "a*", "(a|)", "a{0}", "^", "$", "(b*)c*"] {
+            assert!(nullable(r), "{r:?}");
+        }
+        for r in ["a", "a*b", "(a|b)", "a{2}", "^a"] {
+            assert!(!nullable(r), "{r:?}");
+        }
+    }
+}
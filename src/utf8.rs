@@ -1,9 +1,44 @@
 use crate::utf8::UnicodeError::{OutsideOfRange, SurrogateCodepoint};
+use alloc::string::String;
+use alloc::vec::Vec;
 use thiserror::Error;
 
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Default, Hash)]
+#[cfg_attr(feature = "std", derive(serde::Serialize))]
 pub struct UnicodeCodepoint(u32);
 
+impl UnicodeCodepoint {
+    /// U+0000, the smallest codepoint
+    pub const MIN: UnicodeCodepoint = UnicodeCodepoint(0x00_0000);
+    /// U+10FFFF, the largest codepoint
+    pub const MAX: UnicodeCodepoint = UnicodeCodepoint(0x10_ffff);
+    /// U+0000, the null character
+    pub const NUL: UnicodeCodepoint = UnicodeCodepoint(0x00_0000);
+    /// U+FFFD, used in place of input that can't be decoded
+    pub const REPLACEMENT: UnicodeCodepoint = UnicodeCodepoint(0x00_fffd);
+
+    /// returns: the next codepoint, skipping the surrogates, or `None` if
+    /// this is [`UnicodeCodepoint::MAX`]
+    #[must_use]
+    pub fn succ(self) -> Option<UnicodeCodepoint> {
+        match self.0 {
+            0x00_d7ff => Some(UnicodeCodepoint(0x00_e000)),
+            c if c < UnicodeCodepoint::MAX.0 => Some(UnicodeCodepoint(c + 1)),
+            _ => None,
+        }
+    }
+
+    /// returns: every codepoint from `start` to `end`, inclusive, skipping
+    /// the surrogates; empty if `start > end`
+    pub fn range(
+        start: UnicodeCodepoint,
+        end: UnicodeCodepoint,
+    ) -> impl Iterator<Item = UnicodeCodepoint> {
+        core::iter::successors(Some(start), |c| c.succ())
+            .take_while(move |c| *c <= end)
+    }
+}
+
 impl From<char> for UnicodeCodepoint {
     fn from(value: char) -> Self {
         UnicodeCodepoint(value.into())
@@ -12,7 +47,9 @@ impl From<char> for UnicodeCodepoint {
 
 impl From<UnicodeCodepoint> for char {
     fn from(value: UnicodeCodepoint) -> Self {
-        char::from_u32(value.0).unwrap()
+        // every constructor rejects surrogates, but a conversion shouldn't
+        // be able to panic even if one slipped through
+        char::from_u32(value.0).unwrap_or(char::REPLACEMENT_CHARACTER)
     }
 }
 
@@ -24,7 +61,7 @@ impl TryFrom<u32> for UnicodeCodepoint {
             Ok(UnicodeCodepoint(value))
         } else if value < 0x00_e000 {
             Err(SurrogateCodepoint(value))
-        } else if value < 0x11_0000 {
+        } else if value <= UnicodeCodepoint::MAX.0 {
             Ok(UnicodeCodepoint(value))
         } else {
             Err(OutsideOfRange(value))
@@ -38,6 +75,92 @@ impl From<UnicodeCodepoint> for u32 {
     }
 }
 
+/// codepoints whose simple case folding `char::to_lowercase` doesn't give,
+/// or that no lowercase or uppercase mapping leads back to, with their
+/// folding
+const EXTRA_FOLDS: [(char, char); 48] = [
+    ('\u{00b5}', 'μ'),
+    ('ſ', 's'),
+    ('ǅ', 'ǆ'),
+    ('ǈ', 'ǉ'),
+    ('ǋ', 'ǌ'),
+    ('ǲ', 'ǳ'),
+    ('ς', 'σ'),
+    ('ϐ', 'β'),
+    ('ϑ', 'θ'),
+    ('ϕ', 'φ'),
+    ('ϖ', 'π'),
+    ('ϰ', 'κ'),
+    ('ϱ', 'ρ'),
+    ('ϴ', 'θ'),
+    ('ϵ', 'ε'),
+    ('ẛ', 'ṡ'),
+    ('ẞ', 'ß'),
+    ('ᾈ', 'ᾀ'),
+    ('ᾉ', 'ᾁ'),
+    ('ᾊ', 'ᾂ'),
+    ('ᾋ', 'ᾃ'),
+    ('ᾌ', 'ᾄ'),
+    ('ᾍ', 'ᾅ'),
+    ('ᾎ', 'ᾆ'),
+    ('ᾏ', 'ᾇ'),
+    ('ᾘ', 'ᾐ'),
+    ('ᾙ', 'ᾑ'),
+    ('ᾚ', 'ᾒ'),
+    ('ᾛ', 'ᾓ'),
+    ('ᾜ', 'ᾔ'),
+    ('ᾝ', 'ᾕ'),
+    ('ᾞ', 'ᾖ'),
+    ('ᾟ', 'ᾗ'),
+    ('ᾨ', 'ᾠ'),
+    ('ᾩ', 'ᾡ'),
+    ('ᾪ', 'ᾢ'),
+    ('ᾫ', 'ᾣ'),
+    ('ᾬ', 'ᾤ'),
+    ('ᾭ', 'ᾥ'),
+    ('ᾮ', 'ᾦ'),
+    ('ᾯ', 'ᾧ'),
+    ('ᾼ', 'ᾳ'),
+    ('\u{1fbe}', 'ι'),
+    ('ῌ', 'ῃ'),
+    ('ῼ', 'ῳ'),
+    ('\u{2126}', 'ω'),
+    ('\u{212a}', 'k'),
+    ('\u{212b}', 'å'),
+];
+
+/// Simple (one codepoint to one codepoint) case folding, used by
+/// case-insensitive matching. A codepoint folds to its lowercase form if
+/// that is a single codepoint, apart from a few letters like `ς` and `ſ`,
+/// which fold to `σ` and `s`. Foldings to several codepoints, like `ß` to
+/// `ss`, aren't supported, so such codepoints fold to themselves.
+///
+/// returns: the case folding of `c`
+#[must_use]
+pub fn fold_codepoint(c: UnicodeCodepoint) -> UnicodeCodepoint {
+    let c = char::from(c);
+    if let Some((_, folded)) = EXTRA_FOLDS.iter().find(|(e, _)| *e == c) {
+        return (*folded).into();
+    }
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l.into(),
+        _ => c.into(),
+    }
+}
+
+/// returns: the codepoints that fold to `folded` without being its
+/// lowercase form, see [`fold_codepoint`]
+#[cfg(feature = "std")]
+pub(crate) fn unfold_extra(
+    folded: UnicodeCodepoint,
+) -> impl Iterator<Item = UnicodeCodepoint> {
+    EXTRA_FOLDS
+        .iter()
+        .filter(move |(_, f)| UnicodeCodepoint::from(*f) == folded)
+        .map(|(e, _)| UnicodeCodepoint::from(*e))
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Error)]
 pub enum UnicodeError {
     #[error("surrogate codepoint {0:#034x} (not a valid codepoint)")]
@@ -52,8 +175,8 @@ pub enum Utf8DecodeError {
     UnexpectedEndOfStream,
     #[error("overlong encoding {0:#034x}")]
     OverlongEncoding(u32),
-    #[error("{0:}")]
-    UnicodeError(UnicodeError),
+    #[error("invalid unicode codepoint")]
+    UnicodeError(#[from] UnicodeError),
     #[error("invalid byte sequence {0:#010x}")]
     InvalidByte1(u8),
     #[error("invalid byte sequence {0:#010x}_{1:08x}")]
@@ -66,7 +189,7 @@ pub enum Utf8DecodeError {
 
 #[must_use]
 pub fn encode_utf8(unicode: &[UnicodeCodepoint]) -> Vec<u8> {
-    let mut out = Vec::<u8>::new();
+    let mut out = Vec::<u8>::with_capacity(utf8_len(unicode));
     for UnicodeCodepoint(c) in unicode {
         let c = *c;
         if c < 0x1 << 7 {
@@ -88,6 +211,26 @@ pub fn encode_utf8(unicode: &[UnicodeCodepoint]) -> Vec<u8> {
     out
 }
 
+/// returns: the number of bytes `encode_utf8(unicode)` would return, without
+/// encoding anything
+#[must_use]
+pub fn utf8_len(unicode: &[UnicodeCodepoint]) -> usize {
+    unicode
+        .iter()
+        .map(|UnicodeCodepoint(c)| {
+            if *c < 0x1 << 7 {
+                1
+            } else if *c < 0x1 << 11 {
+                2
+            } else if *c < 0x1 << 16 {
+                3
+            } else {
+                4
+            }
+        })
+        .sum()
+}
+
 #[must_use]
 pub fn encode_utf8_string(unicode: &[UnicodeCodepoint]) -> String {
     unicode.iter().map(|c| char::from(*c)).collect()
@@ -96,88 +239,110 @@ pub fn encode_utf8_string(unicode: &[UnicodeCodepoint]) -> String {
 pub fn decode_utf8(
     utf8: &[u8],
 ) -> Result<Vec<UnicodeCodepoint>, Utf8DecodeError> {
-    let mut out = Vec::<UnicodeCodepoint>::new();
+    decode_utf8_iter(utf8).collect()
+}
+
+/// Like [`decode_utf8`], but decodes one codepoint at a time, so the input
+/// doesn't have to be decoded all at once. The iterator ends after the first
+/// error.
+pub fn decode_utf8_iter(
+    utf8: &[u8],
+) -> impl Iterator<Item = Result<UnicodeCodepoint, Utf8DecodeError>> + '_ {
     let mut iter = utf8.iter();
-    while let Some(b0) = iter.next() {
-        let b0 = u32::from(*b0);
-        if b0 >> 7 == 0 {
-            out.push(UnicodeCodepoint(b0));
-            continue;
+    let mut failed = false;
+    core::iter::from_fn(move || {
+        if failed {
+            return None;
         }
+        let result = decode_next(*iter.next()?, &mut iter);
+        failed = result.is_err();
+        Some(result)
+    })
+}
 
-        let b1 = u32::from(
-            *iter.next().ok_or(Utf8DecodeError::UnexpectedEndOfStream)?,
-        );
-        if b1 >> 6 != 0b10 {
-            return Err(Utf8DecodeError::InvalidByte2(
-                trunc_u8(b0),
-                trunc_u8(b1),
-            ));
-        }
-        if b0 >> 5 == 0b110 {
-            let c = ((b0 & 0b0001_1111) << 6) | (b1 & 0b0011_1111);
-            if c < 0x00_0080 {
-                return Err(Utf8DecodeError::OverlongEncoding(c));
-            }
-            out.push(UnicodeCodepoint(c));
-            continue;
+/// returns: the codepoint starting with `b0`, reading its continuation bytes
+/// from `iter`
+fn decode_next(
+    b0: u8,
+    iter: &mut core::slice::Iter<'_, u8>,
+) -> Result<UnicodeCodepoint, Utf8DecodeError> {
+    let b0 = u32::from(b0);
+    if b0 >> 7 == 0 {
+        return Ok(UnicodeCodepoint(b0));
+    }
+
+    let b1 =
+        u32::from(*iter.next().ok_or(Utf8DecodeError::UnexpectedEndOfStream)?);
+    if b1 >> 6 != 0b10 {
+        return Err(Utf8DecodeError::InvalidByte2(trunc_u8(b0), trunc_u8(b1)));
+    }
+    if b0 >> 5 == 0b110 {
+        let c = ((b0 & 0b0001_1111) << 6) | (b1 & 0b0011_1111);
+        if c < 0x00_0080 {
+            return Err(Utf8DecodeError::OverlongEncoding(c));
         }
+        return Ok(UnicodeCodepoint(c));
+    }
 
-        let b2 = u32::from(
-            *iter.next().ok_or(Utf8DecodeError::UnexpectedEndOfStream)?,
-        );
-        if b2 >> 6 != 0b10 {
-            return Err(Utf8DecodeError::InvalidByte3(
-                trunc_u8(b0),
-                trunc_u8(b1),
-                trunc_u8(b2),
-            ));
+    let b2 =
+        u32::from(*iter.next().ok_or(Utf8DecodeError::UnexpectedEndOfStream)?);
+    if b2 >> 6 != 0b10 {
+        return Err(Utf8DecodeError::InvalidByte3(
+            trunc_u8(b0),
+            trunc_u8(b1),
+            trunc_u8(b2),
+        ));
+    }
+    if b0 >> 4 == 0b1110 {
+        let c = ((b0 & 0b0000_1111) << 12)
+            | ((b1 & 0b0011_1111) << 6)
+            | (b2 & 0b0011_1111);
+        if c < 0x00_0800 {
+            return Err(Utf8DecodeError::OverlongEncoding(c));
         }
-        if b0 >> 4 == 0b1110 {
-            let c = ((b0 & 0b0000_1111) << 12)
-                | ((b1 & 0b0011_1111) << 6)
-                | (b2 & 0b0011_1111);
-            if c < 0x00_0800 {
-                return Err(Utf8DecodeError::OverlongEncoding(c));
-            }
-            if (0x00_d800..0x00_e000).contains(&c) {
-                return Err(Utf8DecodeError::UnicodeError(SurrogateCodepoint(
-                    c,
-                )));
-            }
-            out.push(UnicodeCodepoint(c));
-            continue;
+        if (0x00_d800..0x00_e000).contains(&c) {
+            return Err(Utf8DecodeError::UnicodeError(SurrogateCodepoint(c)));
         }
+        return Ok(UnicodeCodepoint(c));
+    }
 
-        let b3 = u32::from(
-            *iter.next().ok_or(Utf8DecodeError::UnexpectedEndOfStream)?,
-        );
-        if b3 >> 6 != 0b10 {
-            return Err(Utf8DecodeError::InvalidByte4(
-                trunc_u8(b0),
-                trunc_u8(b1),
-                trunc_u8(b2),
-                trunc_u8(b3),
-            ));
+    let b3 =
+        u32::from(*iter.next().ok_or(Utf8DecodeError::UnexpectedEndOfStream)?);
+    if b3 >> 6 != 0b10 {
+        return Err(Utf8DecodeError::InvalidByte4(
+            trunc_u8(b0),
+            trunc_u8(b1),
+            trunc_u8(b2),
+            trunc_u8(b3),
+        ));
+    }
+    if b0 >> 3 == 0b1_1110 {
+        let c = ((b0 & 0b0000_0111) << 18)
+            | ((b1 & 0b0011_1111) << 12)
+            | ((b2 & 0b0011_1111) << 6)
+            | (b3 & 0b0011_1111);
+        if c < 0x01_0000 {
+            return Err(Utf8DecodeError::OverlongEncoding(c));
         }
-        if b0 >> 3 == 0b1_1110 {
-            let c = ((b0 & 0b0000_0111) << 18)
-                | ((b1 & 0b0011_1111) << 12)
-                | ((b2 & 0b0011_1111) << 6)
-                | (b3 & 0b0011_1111);
-            if c < 0x01_0000 {
-                return Err(Utf8DecodeError::OverlongEncoding(c));
-            }
-            out.push(UnicodeCodepoint(c));
-            continue;
+        if c > UnicodeCodepoint::MAX.0 {
+            return Err(Utf8DecodeError::UnicodeError(OutsideOfRange(c)));
         }
-
-        // invalid first byte sequence, matching one of these patterns:
-        // 10xxxxxx
-        // 11111xxx
-        return Err(Utf8DecodeError::InvalidByte1(trunc_u8(b0)));
+        return Ok(UnicodeCodepoint(c));
     }
-    Ok(out)
+
+    // invalid first byte sequence, matching one of these patterns:
+    // 10xxxxxx
+    // 11111xxx
+    Err(Utf8DecodeError::InvalidByte1(trunc_u8(b0)))
+}
+
+/// Like [`decode_utf8`], but strips a leading byte order mark (`EF BB BF`)
+/// if there is one. [`decode_utf8`] keeps it as U+FEFF so that decoding and
+/// encoding round-trips exactly.
+pub fn decode_utf8_skip_bom(
+    utf8: &[u8],
+) -> Result<Vec<UnicodeCodepoint>, Utf8DecodeError> {
+    decode_utf8(utf8.strip_prefix(b"\xef\xbb\xbf").unwrap_or(utf8))
 }
 
 #[allow(clippy::cast_possible_truncation)]
@@ -189,6 +354,35 @@ fn trunc_u8(x: u32) -> u8 {
 mod tests {
     use super::*;
 
+    #[test]
+    fn fold() {
+        let fold = |c: char| char::from(fold_codepoint(c.into()));
+        assert_eq!(fold('É'), 'é');
+        assert_eq!(fold('é'), 'é');
+        assert_eq!(fold('A'), 'a');
+        assert_eq!(fold('Σ'), 'σ');
+        assert_eq!(fold('ς'), 'σ');
+        assert_eq!(fold('Ж'), 'ж');
+        assert_eq!(fold('ß'), 'ß');
+        assert_eq!(fold('1'), '1');
+
+        assert_eq!(fold('\u{2126}'), 'ω');
+        assert_eq!(fold('ẞ'), 'ß');
+
+        // every extra codepoint either shares its uppercase form with its
+        // folding, or is a lowercase mapping that doesn't map back
+        for (e, f) in EXTRA_FOLDS {
+            assert!(f.to_lowercase().eq([f]), "{f:?}");
+            assert!(
+                e.to_uppercase().eq(f.to_uppercase())
+                    || e.to_lowercase().eq([f]),
+                "{e:?}"
+            );
+        }
+        #[cfg(feature = "std")]
+        assert_eq!(unfold_extra('θ'.into()).count(), 2);
+    }
+
     #[test]
     fn to_unicode() {
         for i in (0x00_0000u32..0x00_d800).chain(0x00_e000..0x11_0000) {
@@ -200,6 +394,50 @@ mod tests {
         }
     }
 
+    #[test]
+    fn unicode_constants() {
+        assert_eq!(char::from(UnicodeCodepoint::REPLACEMENT), '\u{FFFD}');
+        assert_eq!(char::from(UnicodeCodepoint::NUL), '\0');
+        assert_eq!(char::from(UnicodeCodepoint::MIN), '\0');
+        assert_eq!(char::from(UnicodeCodepoint::MAX), char::MAX);
+        assert_eq!(
+            UnicodeCodepoint::try_from(u32::from(UnicodeCodepoint::MAX) + 1),
+            Err(OutsideOfRange(0x11_0000))
+        );
+    }
+
+    #[test]
+    fn unicode_succ() {
+        let succ = |c: u32| UnicodeCodepoint(c).succ().map(|c| c.0);
+        assert_eq!(succ(0x41), Some(0x42));
+        assert_eq!(succ(0x00_d7ff), Some(0x00_e000));
+        assert_eq!(succ(0x10_fffe), Some(0x10_ffff));
+        assert_eq!(succ(0x10_ffff), None);
+
+        let range = |start: char, end: char| {
+            UnicodeCodepoint::range(start.into(), end.into())
+                .map(char::from)
+                .collect::<String>()
+        };
+        assert_eq!(range('A', 'C'), "ABC");
+        assert_eq!(range('A', 'A'), "A");
+        assert_eq!(range('C', 'A'), "");
+        assert_eq!(range('\u{10fffe}', char::MAX), "\u{10fffe}\u{10ffff}");
+        // the surrogates in between are skipped
+        assert_eq!(
+            range('\u{d7fe}', '\u{e001}'),
+            "\u{d7fe}\u{d7ff}\u{e000}\u{e001}"
+        );
+        assert_eq!(
+            UnicodeCodepoint::range(
+                UnicodeCodepoint::MIN,
+                UnicodeCodepoint::MAX
+            )
+            .count(),
+            0x11_0000 - 0x800
+        );
+    }
+
     #[test]
     fn to_unicode_invalid() {
         for i in 0x00_d800..0x00_e000 {
@@ -222,12 +460,51 @@ mod tests {
             let unicode = decode_utf8(bytes).unwrap();
             let encoded = encode_utf8(&unicode);
             assert_eq!(bytes, encoded);
+            assert_eq!(utf8_len(&unicode), encoded.len());
         }
     }
 
+    #[test]
+    fn utf8_bom() {
+        let bom = UnicodeCodepoint(0xfeff);
+        let a = UnicodeCodepoint::from('a');
+
+        assert_eq!(decode_utf8(b"\xef\xbb\xbfa").unwrap(), [bom, a]);
+        assert_eq!(decode_utf8_skip_bom(b"\xef\xbb\xbfa").unwrap(), [a]);
+        assert_eq!(decode_utf8_skip_bom(b"a").unwrap(), [a]);
+        assert_eq!(decode_utf8_skip_bom(b"").unwrap(), []);
+        // only a leading mark is stripped
+        assert_eq!(
+            decode_utf8_skip_bom(b"\xef\xbb\xbf\xef\xbb\xbfa").unwrap(),
+            [bom, a]
+        );
+        assert_eq!(decode_utf8_skip_bom(b"a\xef\xbb\xbf").unwrap(), [a, bom]);
+        assert!(decode_utf8_skip_bom(b"\xef\xbb").is_err());
+    }
+
+    #[test]
+    fn utf8_decode_iter() {
+        let a = UnicodeCodepoint::from('a');
+        let fire = UnicodeCodepoint::from('🔥');
+
+        let mut iter = decode_utf8_iter("a🔥".as_bytes());
+        assert_eq!(iter.next(), Some(Ok(a)));
+        assert_eq!(iter.next(), Some(Ok(fire)));
+        assert_eq!(iter.next(), None);
+
+        // decoding stops at the first error
+        let mut iter = decode_utf8_iter(b"a\xa0a");
+        assert_eq!(iter.next(), Some(Ok(a)));
+        assert_eq!(
+            iter.next(),
+            Some(Err(Utf8DecodeError::InvalidByte2(0xa0, b'a')))
+        );
+        assert_eq!(iter.next(), None);
+    }
+
     #[test]
     fn utf8_invalid() {
-        let strings: [&[u8]; 7] = [
+        let strings: [&[u8]; 8] = [
             &[0xc3, 0x28],
             &[0xa0, 0xa1],
             &[0xe2, 0x28, 0xa1],
@@ -235,9 +512,19 @@ mod tests {
             &[0xf0, 0x28, 0x8c, 0xbc],
             &[0xf0, 0x90, 0x28, 0xbc],
             &[0xf0, 0x28, 0x8c, 0x28],
+            &[0xf4, 0x90, 0x80, 0x80],
         ];
         for s in strings {
             assert!(matches!(decode_utf8(s), Err(..)));
         }
+        // well-formed, but past U+10FFFF
+        assert_eq!(
+            decode_utf8(&[0xf4, 0x90, 0x80, 0x80]),
+            Err(Utf8DecodeError::UnicodeError(OutsideOfRange(0x11_0000)))
+        );
+        assert_eq!(
+            decode_utf8(&[0xf4, 0x8f, 0xbf, 0xbf]),
+            Ok(Vec::from([UnicodeCodepoint::MAX]))
+        );
     }
 }
@@ -0,0 +1,84 @@
+//! Checks `Regex::find`, `Regex::find_all` and `Regex::test` against the
+//! brute-force matcher in `reference`, see also the `find_reference` fuzz
+//! target.
+#![cfg(feature = "std")]
+
+mod reference;
+
+use leben_regex::{Regex, decode_utf8};
+
+/// Asserts that `pattern` finds the same match in `string` as the reference
+/// matcher, if it compiles
+fn check(pattern: &[u8], string: &str) {
+    let Ok(regex) = Regex::new(pattern) else {
+        return;
+    };
+    let ast = Regex::parse_ast(pattern).unwrap();
+    let string = decode_utf8(string.as_bytes()).unwrap();
+    let pattern = String::from_utf8_lossy(pattern);
+    assert_eq!(
+        regex.find(&string),
+        reference::find(&ast.root.node, &string, false),
+        "find {pattern:?} in {string:?}"
+    );
+    assert_eq!(
+        regex.test(&string),
+        reference::test(&ast.root.node, &string, false),
+        "test {pattern:?} on {string:?}"
+    );
+    assert_eq!(
+        regex.find_all(&string),
+        reference::find_all(&ast.root.node, &string, false),
+        "find_all {pattern:?} in {string:?}"
+    );
+}
+
+#[test]
+fn find_matches_reference() {
+    // every pattern of up to 4 bytes from the syntax characters, on every
+    // string of up to 3 codepoints
+    let bytes = b"ab|*()^$";
+    let strings: Vec<String> = (0..=3)
+        .flat_map(|len| {
+            (0..1_usize << len).map(move |k| {
+                (0..len)
+                    .map(|i| if (k >> i) & 1 == 0 { 'a' } else { 'b' })
+                    .collect()
+            })
+        })
+        .collect();
+    for len in 0..=4 {
+        for k in 0..bytes.len().pow(len) {
+            let pattern: Vec<_> = (0..len)
+                .map(|i| bytes[k / bytes.len().pow(i) % bytes.len()])
+                .collect();
+            for string in &strings {
+                check(&pattern, string);
+            }
+        }
+    }
+}
+
+#[test]
+fn find_matches_reference_regressions() {
+    // anchors in the middle of a pattern
+    check(b"a{2}|b*$", "bab");
+    check(b"(a|^b)*b", "bab");
+    check(b"(^|a)b", "abb");
+    check(b"b(a|$)*", "aba");
+    check(b"$a|a", "ba");
+
+    // codepoints whose lowercase form doesn't uppercase back to them, which
+    // case insensitive literals of that lowercase form didn't match
+    check("(?i:k)".as_bytes(), "\u{212a}");
+    check("(?i:K)".as_bytes(), "\u{212a}");
+    check("(?i:\u{212a})".as_bytes(), "kK");
+    check("(?i:å)".as_bytes(), "\u{212b}");
+    check("(?i:ω)".as_bytes(), "\u{2126}");
+    check("(?i:\u{2126})".as_bytes(), "Ωω");
+    check("(?i:θ)".as_bytes(), "ϴ");
+    check("(?i:ϑ)".as_bytes(), "ϴ");
+    check("(?i:ß)".as_bytes(), "ẞ");
+    check("(?i:ᾀ)".as_bytes(), "ᾈ");
+    check("(?i:ῳ)*".as_bytes(), "ῼῳ");
+}
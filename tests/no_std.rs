@@ -0,0 +1,41 @@
+//! Smoke test for the `alloc`-only build, run with
+//! `cargo test --no-default-features --test no_std`.
+#![no_std]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use leben_regex::{Pattern, Regex, UnicodeCodepoint, decode_utf8, encode_utf8};
+
+#[test]
+fn utf8_round_trip() {
+    let bytes = "a🔥中".as_bytes();
+    let unicode = decode_utf8(bytes).unwrap();
+    assert_eq!(unicode.len(), 3);
+    assert_eq!(unicode[1], UnicodeCodepoint::from('🔥'));
+    assert_eq!(encode_utf8(&unicode), bytes);
+}
+
+#[test]
+fn regex_from_pattern() {
+    use Pattern::{Concat, Group, Star, Token};
+
+    // patterns can't be parsed without `std`, so this is `a(b*)c`
+    let c = |c: char| Token(UnicodeCodepoint::from(c));
+    let pattern = Concat(Vec::from([
+        c('a'),
+        Group(Box::new(Star(Box::new(c('b'))))),
+        c('c'),
+    ]));
+    let regex = Regex::from_pattern(&pattern).unwrap();
+
+    let string = decode_utf8("xabbc".as_bytes()).unwrap();
+    assert!(regex.test(&string[1..]));
+    assert!(!regex.test(&string));
+    assert_eq!(regex.find(&string), Some((1, 4)));
+    assert_eq!(
+        regex.captures(&string),
+        Some(Vec::from([Some((1, 4)), Some((2, 2))]))
+    );
+}
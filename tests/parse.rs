@@ -0,0 +1,72 @@
+//! Smoke test for the parsing entry points, as seen from outside the crate.
+#![cfg(feature = "std")]
+
+use leben_regex::parse::{Atom, Quantifier};
+use leben_regex::{Regex, RegexError, RegexParseError, decode_utf8};
+
+#[test]
+fn parse_entry_points() {
+    let regex = Regex::new(b"a(b|c)*").unwrap();
+    assert!(regex.test(&decode_utf8(b"abcb").unwrap()));
+    assert!(Regex::new_from_str("a(b|c)*").unwrap() == regex);
+
+    let ast = Regex::parse_ast(b"a(b|c)*").unwrap();
+    let parts = &ast.root.node.alts.nodes[0].parts.nodes;
+    assert!(matches!(parts[1].atom, Atom::Capture { .. }));
+
+    assert!(matches!(Regex::new(b"a)"), Err(RegexError::ParseError(_))));
+}
+
+#[test]
+fn parse_ast_tree() {
+    let ast = Regex::parse_ast(b"a(b|c)*").unwrap();
+    let alts = &ast.root.node.alts.nodes;
+    assert_eq!(alts.len(), 1);
+    let parts = &alts[0].parts.nodes;
+    assert_eq!(parts.len(), 2);
+    assert!(matches!(parts[0].atom, Atom::Character(_)));
+    assert!(parts[0].quantifier.is_none());
+    let Atom::Capture { alt, .. } = &parts[1].atom else {
+        panic!("expected a group");
+    };
+    assert_eq!(alt.alts.nodes.len(), 2);
+    assert!(matches!(parts[1].quantifier, Some(Quantifier::Star(_))));
+
+    /// returns: how many objects in `json` have a field named `key`
+    fn count_key(json: &serde_json::Value, key: &str) -> usize {
+        match json {
+            serde_json::Value::Object(fields) => {
+                usize::from(fields.contains_key(key))
+                    + fields.values().map(|v| count_key(v, key)).sum::<usize>()
+            }
+            serde_json::Value::Array(items) => {
+                items.iter().map(|v| count_key(v, key)).sum()
+            }
+            _ => 0,
+        }
+    }
+    let to_json = |pattern: &[u8]| {
+        serde_json::to_value(Regex::parse_ast(pattern).unwrap()).unwrap()
+    };
+
+    // the JSON has every node of the tree: two alternations, three
+    // concatenations, four atoms, one of them a group, and one star
+    let json = serde_json::to_value(&ast).unwrap();
+    assert_eq!(count_key(&json, "root"), 1);
+    assert_eq!(count_key(&json, "alts"), 2);
+    assert_eq!(count_key(&json, "parts"), 3);
+    assert_eq!(count_key(&json, "atom"), 4);
+    assert_eq!(count_key(&json, "quantifier"), 4);
+    assert_eq!(count_key(&json, "Capture"), 1);
+    assert_eq!(count_key(&json, "Star"), 1);
+    // and tells apart patterns that differ anywhere
+    assert_eq!(json, to_json(b"a(b|c)*"));
+    for other in [&b"a(b|d)*"[..], b"a(c|b)*", b"a(b|c)", b"(b|c)*"] {
+        assert_ne!(json, to_json(other), "{other:?}");
+    }
+
+    assert!(matches!(
+        Regex::parse_ast(b"a)"),
+        Err(RegexParseError::ParseError { .. })
+    ));
+}
@@ -0,0 +1,167 @@
+//! A brute-force reference matcher to check [`Regex::find`] against. It
+//! walks the syntax tree of a pattern directly, so it shares nothing with the
+//! automaton but the parser. Used by `tests/find.rs` and the
+//! `find_reference` fuzz target.
+//!
+//! [`Regex::find`]: leben_regex::Regex::find
+
+use leben_regex::parse::{
+    AltExpr, Atom, ConcatExpr, GroupKind, KleeneExpr, Quantifier,
+};
+use leben_regex::{UnicodeCodepoint, fold_codepoint};
+use std::collections::BTreeSet;
+
+/// Tries every start index of `string` in order, and every end index from
+/// there, like [`Regex::find`] should: the match that starts first wins, and
+/// of those the shortest.
+///
+/// returns: the starting index and length of the first match of `alt`, if any
+///
+/// [`Regex::find`]: leben_regex::Regex::find
+pub fn find(
+    alt: &AltExpr,
+    string: &[UnicodeCodepoint],
+    case_insensitive: bool,
+) -> Option<(usize, usize)> {
+    (0..=string.len()).find_map(|start| {
+        let ends = match_alt(alt, string, start, case_insensitive);
+        ends.first().map(|end| (start, end - start))
+    })
+}
+
+/// Repeats [`find`] after the end of every match, like [`Regex::find_all`]
+/// should: at the start of each match, a non-empty match comes before the
+/// empty one, and after an empty match the search goes on one index later.
+///
+/// returns: the starting index and length of every match of `alt`
+///
+/// [`Regex::find_all`]: leben_regex::Regex::find_all
+pub fn find_all(
+    alt: &AltExpr,
+    string: &[UnicodeCodepoint],
+    case_insensitive: bool,
+) -> Vec<(usize, usize)> {
+    let mut matches = Vec::new();
+    let mut offset = 0;
+    while let Some((start, ends)) = (offset..=string.len())
+        .map(|start| (start, match_alt(alt, string, start, case_insensitive)))
+        .find(|(_, ends)| !ends.is_empty())
+    {
+        let end = ends.range(start + 1..).next().copied().unwrap_or(start);
+        matches.push((start, end - start));
+        offset = if end == start { start + 1 } else { end };
+    }
+    matches
+}
+
+/// returns: whether all of `string` matches `alt`, like [`Regex::test`]
+///
+/// [`Regex::test`]: leben_regex::Regex::test
+pub fn test(
+    alt: &AltExpr,
+    string: &[UnicodeCodepoint],
+    case_insensitive: bool,
+) -> bool {
+    match_alt(alt, string, 0, case_insensitive).contains(&string.len())
+}
+
+/// returns: every index that a match of `alt` starting at `start` can end at
+fn match_alt(
+    alt: &AltExpr,
+    string: &[UnicodeCodepoint],
+    start: usize,
+    case_insensitive: bool,
+) -> BTreeSet<usize> {
+    alt.alts
+        .nodes
+        .iter()
+        .flat_map(|c| match_concat(c, string, start, case_insensitive))
+        .collect()
+}
+
+fn match_concat(
+    concat: &ConcatExpr,
+    string: &[UnicodeCodepoint],
+    start: usize,
+    case_insensitive: bool,
+) -> BTreeSet<usize> {
+    let mut ends = BTreeSet::from([start]);
+    for part in &concat.parts.nodes {
+        ends = ends
+            .into_iter()
+            .flat_map(|end| match_kleene(part, string, end, case_insensitive))
+            .collect();
+    }
+    ends
+}
+
+fn match_kleene(
+    kleene: &KleeneExpr,
+    string: &[UnicodeCodepoint],
+    start: usize,
+    case_insensitive: bool,
+) -> BTreeSet<usize> {
+    let atom = |from: &BTreeSet<usize>| -> BTreeSet<usize> {
+        from.iter()
+            .flat_map(|end| {
+                match_atom(&kleene.atom, string, *end, case_insensitive)
+            })
+            .collect()
+    };
+    match &kleene.quantifier {
+        None => atom(&BTreeSet::from([start])),
+        Some(Quantifier::Star(_)) => {
+            // repeat until no new end index is found
+            let mut ends = BTreeSet::from([start]);
+            let mut new = ends.clone();
+            while !new.is_empty() {
+                new = atom(&new).difference(&ends).copied().collect();
+                ends.extend(&new);
+            }
+            ends
+        }
+        Some(Quantifier::Count { count, .. }) => {
+            let count = count.value().expect("count should fit in a usize");
+            (0..count).fold(BTreeSet::from([start]), |ends, _| atom(&ends))
+        }
+    }
+}
+
+fn match_atom(
+    atom: &Atom,
+    string: &[UnicodeCodepoint],
+    start: usize,
+    case_insensitive: bool,
+) -> BTreeSet<usize> {
+    let only_if = |matched: bool, end: usize| {
+        if matched {
+            BTreeSet::from([end])
+        } else {
+            BTreeSet::new()
+        }
+    };
+    match atom {
+        Atom::Character(c) => {
+            let c = c.to_codepoint().expect("pattern should be valid UTF-8");
+            let matched = string.get(start).is_some_and(|s| {
+                if case_insensitive {
+                    fold_codepoint(*s) == fold_codepoint(c)
+                } else {
+                    *s == c
+                }
+            });
+            only_if(matched, start + 1)
+        }
+        Atom::StartAnchor(_) => only_if(start == 0, start),
+        Atom::EndAnchor(_) => only_if(start == string.len(), start),
+        Atom::Capture { kind, alt, .. } => {
+            // a flag only applies inside its group
+            let case_insensitive = match kind {
+                Some(GroupKind::CaseInsensitive) => true,
+                Some(GroupKind::CaseSensitive) => false,
+                _ => case_insensitive,
+            };
+            match_alt(alt, string, start, case_insensitive)
+        }
+    }
+}